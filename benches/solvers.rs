@@ -0,0 +1,215 @@
+//! Per frame cost of the fingerprint solvers, with the reference images
+//! hashed once when the solver is created against hashing them again on every
+//! frame like the solvers used to, and of hashing on the shared pool against a
+//! thread per image. Both solves crop the same regions and match the parts
+//! with `best_assignment` using the configured matcher, only the reference
+//! hashes differ.
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use gta_assistant::constants::{self, R1080};
+use gta_assistant::layout::{Layouts, TaskLayout};
+use gta_assistant::matcher::{self, Features, Matcher};
+use gta_assistant::solver::assignment::best_assignment;
+use gta_assistant::solver::{casino::CasinoSolver, cayo::CayoSolver, reference_assets};
+use gta_assistant::thresholds::Thresholds;
+use gta_assistant::utils::{self, Region};
+use image::RgbImage;
+use std::hint::black_box;
+use std::path::Path;
+use std::thread;
+
+/// Reference images of a task, as the solvers used to keep them.
+struct Images {
+    header: RgbImage,
+    fingerprints: Vec<RgbImage>,
+    parts: Vec<Vec<RgbImage>>,
+}
+
+impl Images {
+    fn load(folder: &Path, fingerprint_count: u16, fingerprint_file: &str, parts: usize) -> Self {
+        let load = |path| utils::load_image(path).unwrap();
+        Images {
+            header: load(folder.join("header.png")),
+            fingerprints: (1..=fingerprint_count)
+                .map(|i| load(folder.join(i.to_string()).join(fingerprint_file)))
+                .collect(),
+            parts: (1..=fingerprint_count)
+                .map(|i| {
+                    (1..=parts)
+                        .map(|part| load(folder.join(i.to_string()).join(format!("{}.png", part))))
+                        .collect()
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Similarity between `target` and each of `images`, all of them hashed
+/// again.
+fn rehash(matcher: &dyn Matcher, target: &RgbImage, images: &[RgbImage]) -> Vec<f64> {
+    let target = matcher.features(target);
+    matcher::compare(matcher, &target, &matcher::features_of(matcher, images)).unwrap()
+}
+
+/// Hashes `images` with a thread each, like the matching used to.
+fn features_scoped(matcher: &dyn Matcher, images: &[RgbImage]) -> Vec<Features> {
+    thread::scope(|scope| {
+        let threads: Vec<_> = images
+            .iter()
+            .map(|image| scope.spawn(move || matcher.features(image)))
+            .collect();
+        threads
+            .into_iter()
+            .map(|thread| thread.join().unwrap())
+            .collect()
+    })
+}
+
+fn crops(frame: &RgbImage, layout: &TaskLayout) -> (RgbImage, Vec<RgbImage>) {
+    let crop = |region: &Region| utils::crop_region(frame, region);
+    (
+        crop(&layout.fingerprint),
+        layout.parts.iter().map(crop).collect(),
+    )
+}
+
+fn casino(c: &mut Criterion) {
+    // hashing costs the same whatever the screen shows
+    let frame = RgbImage::new(R1080.0, R1080.1);
+    let layout = Layouts::embedded().unwrap().get(R1080).casino;
+    let thresholds = Thresholds::embedded().unwrap().casino;
+    let matcher = thresholds.matcher.build().unwrap();
+    let matcher = matcher.as_ref();
+    let solver = CasinoSolver::with_layout(R1080, layout.clone(), thresholds).unwrap();
+    let (folder, _) = reference_assets(R1080, "casino");
+    let images = Images::load(&folder, *constants::CASINO_FINGERPRINT_COUNT, "full.png", 4);
+
+    let mut group = c.benchmark_group("casino");
+    group.bench_function("header/rehashed", |b| {
+        b.iter(|| {
+            let header = utils::crop_region(&frame, &layout.header);
+            rehash(matcher, &header, std::slice::from_ref(&images.header))[0]
+        })
+    });
+    group.bench_function("header/cached", |b| {
+        b.iter(|| {
+            let header = utils::crop_region(&frame, solver.header_pos());
+            solver.header_similarity(&header).unwrap()
+        })
+    });
+    group.bench_function("solve/rehashed", |b| {
+        b.iter(|| {
+            let (fingerprint, slots) = crops(&frame, &layout);
+            let scores = rehash(matcher, &fingerprint, &images.fingerprints);
+            let index = utils::best_match(&scores).index;
+            let scores: Vec<Vec<f64>> = images.parts[index]
+                .iter()
+                .map(|part| rehash(matcher, part, &slots))
+                .collect();
+            best_assignment(black_box(&scores))
+        })
+    });
+    group.bench_function("solve/cached", |b| {
+        b.iter(|| {
+            let (fingerprint, slots) = crops(&frame, &layout);
+            solver
+                .solve_regions(black_box(&fingerprint), &slots)
+                .unwrap()
+        })
+    });
+    group.finish();
+}
+
+fn cayo(c: &mut Criterion) {
+    let frame = RgbImage::new(R1080.0, R1080.1);
+    let layout = Layouts::embedded().unwrap().get(R1080).cayo;
+    let thresholds = Thresholds::embedded().unwrap().cayo;
+    let matcher = thresholds.matcher.build().unwrap();
+    let matcher = matcher.as_ref();
+    let solver = CayoSolver::with_layout(R1080, layout.clone(), thresholds).unwrap();
+    let (folder, _) = reference_assets(R1080, "cayo");
+    let images = Images::load(
+        &folder,
+        *constants::CAYO_FINGERPRINT_COUNT,
+        "fingerprint.png",
+        8,
+    );
+
+    let mut group = c.benchmark_group("cayo");
+    group.bench_function("header/rehashed", |b| {
+        b.iter(|| {
+            let header = utils::crop_region(&frame, &layout.header);
+            rehash(matcher, &header, std::slice::from_ref(&images.header))[0]
+        })
+    });
+    group.bench_function("header/cached", |b| {
+        b.iter(|| {
+            let header = utils::crop_region(&frame, solver.header_pos());
+            solver.header_similarity(&header).unwrap()
+        })
+    });
+    group.bench_function("solve/rehashed", |b| {
+        b.iter(|| {
+            let (fingerprint, rows) = crops(&frame, &layout);
+            let scores = rehash(matcher, &fingerprint, &images.fingerprints);
+            let index = utils::best_match(&scores).index;
+            let scores: Vec<Vec<f64>> = rows
+                .iter()
+                .map(|row| rehash(matcher, row, &images.parts[index]))
+                .collect();
+            best_assignment(black_box(&scores))
+        })
+    });
+    group.bench_function("solve/cached", |b| {
+        b.iter(|| {
+            let (fingerprint, rows) = crops(&frame, &layout);
+            solver
+                .solve_regions(black_box(&fingerprint), &rows)
+                .unwrap()
+        })
+    });
+    group.finish();
+}
+
+fn threads(c: &mut Criterion) {
+    let (folder, _) = reference_assets(R1080, "cayo");
+    let images = Images::load(
+        &folder,
+        *constants::CAYO_FINGERPRINT_COUNT,
+        "fingerprint.png",
+        8,
+    );
+    let references: Vec<RgbImage> = images
+        .fingerprints
+        .into_iter()
+        .chain(images.parts.into_iter().flatten())
+        .collect();
+    let layout = Layouts::embedded().unwrap().get(R1080).cayo;
+    let (fingerprint, rows) = crops(&RgbImage::new(R1080.0, R1080.1), &layout);
+    let crops: Vec<RgbImage> = [fingerprint].into_iter().chain(rows).collect();
+    let matcher = Thresholds::embedded()
+        .unwrap()
+        .cayo
+        .matcher
+        .build()
+        .unwrap();
+    let matcher = matcher.as_ref();
+
+    let mut group = c.benchmark_group("threads");
+    group.bench_function("references/scoped", |b| {
+        b.iter(|| features_scoped(matcher, black_box(&references)))
+    });
+    group.bench_function("references/pool", |b| {
+        b.iter(|| matcher::features_of(matcher, black_box(&references)))
+    });
+    group.bench_function("cayo crops/scoped", |b| {
+        b.iter(|| features_scoped(matcher, black_box(&crops)))
+    });
+    group.bench_function("cayo crops/pool", |b| {
+        b.iter(|| matcher::features_of(matcher, black_box(&crops)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, casino, cayo, threads);
+criterion_main!(benches);
@@ -1,7 +1,6 @@
-#[cfg(not(target_os = "windows"))]
-compile_error!("This project can only be built on Windows.");
 fn main() {
-    if cfg!(target_os = "windows") {
+    // build scripts run on the host, so look at the target we are building for
+    if std::env::var("CARGO_CFG_TARGET_OS").unwrap() == "windows" {
         let mut res = winresource::WindowsResource::new();
         res.set_icon("assets/icon.ico");
         res.compile().expect("error in build script");
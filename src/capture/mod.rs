@@ -0,0 +1,225 @@
+use crate::constants;
+use crate::error::TaskError;
+use crate::utils::{self, Region, Resolution, ThreadStatus};
+use image::{RgbImage, RgbaImage};
+use std::mem;
+use std::slice;
+use std::time::{Duration, Instant};
+
+mod monitor;
+mod replay;
+
+pub use monitor::MonitorSource;
+pub use replay::ReplaySource;
+
+pub trait FrameSource: Send {
+    fn resolution(&self) -> Resolution;
+
+    /// Moves on to the next frame. Live sources always show the current
+    /// screen, so there is nothing to do for them.
+    fn next_frame(&mut self) -> Result<(), TaskError> {
+        Ok(())
+    }
+
+    fn capture(&mut self) -> Result<RgbaImage, TaskError>;
+
+    /// Captures every region from a single grab into `crops`, reusing their
+    /// buffers from the previous call when the sizes match.
+    fn capture_regions_into(
+        &mut self,
+        regions: &[Region],
+        crops: &mut Vec<RgbImage>,
+    ) -> Result<(), TaskError> {
+        let frame = self.capture()?;
+        crop_all(&frame, (0, 0), regions, crops);
+        Ok(())
+    }
+
+    fn capture_region(&mut self, region: &Region) -> Result<RgbImage, TaskError> {
+        let mut crops = Vec::with_capacity(1);
+        self.capture_regions_into(slice::from_ref(region), &mut crops)?;
+        Ok(crops.remove(0))
+    }
+
+    /// Returns every requested region from a single capture.
+    fn capture_regions(&mut self, regions: &[Region]) -> Result<Vec<RgbImage>, TaskError> {
+        let mut crops = Vec::with_capacity(regions.len());
+        self.capture_regions_into(regions, &mut crops)?;
+        Ok(crops)
+    }
+
+    /// Whether the source will never produce another frame (end of a replay).
+    fn is_exhausted(&self) -> bool {
+        false
+    }
+}
+
+/// Crops `regions` out of `image`, whose top left corner is at `origin` on the
+/// screen, into `crops`.
+fn crop_all(image: &RgbaImage, origin: (u32, u32), regions: &[Region], crops: &mut Vec<RgbImage>) {
+    crops.truncate(regions.len());
+    crops.resize_with(regions.len(), RgbImage::default);
+    for (region, crop) in regions.iter().zip(crops.iter_mut()) {
+        utils::crop_into(image, origin, region, crop);
+    }
+}
+
+/// Captures `regions` into `crops` on consecutive frames until two of them
+/// differ by at most `tolerance` (mean change from 0 to 1) in every region,
+/// so that screens still animating in are not solved. Returns false when they
+/// kept changing for `max_wait`, `crops` then holding the last frame. A
+/// replay running out of frames counts as stable.
+pub fn wait_until_stable(
+    frames: &mut dyn FrameSource,
+    thread_status: &ThreadStatus,
+    regions: &[Region],
+    crops: &mut Vec<RgbImage>,
+    tolerance: f64,
+    max_wait: Duration,
+) -> Result<bool, TaskError> {
+    let start = Instant::now();
+    frames.capture_regions_into(regions, crops)?;
+    let mut previous = Vec::with_capacity(regions.len());
+    loop {
+        if frames.is_exhausted() {
+            return Ok(true);
+        }
+        if start.elapsed() >= max_wait {
+            return Ok(false);
+        }
+        if !thread_status.sleep(*constants::STABLE_FRAME_INTERVAL) {
+            return Err(TaskError::Cancelled);
+        }
+        mem::swap(crops, &mut previous);
+        frames.next_frame()?;
+        frames.capture_regions_into(regions, crops)?;
+        let change = previous
+            .iter()
+            .zip(crops.iter())
+            .map(|(previous, crop)| utils::mean_difference(previous, crop))
+            .fold(0.0, f64::max);
+        if change <= tolerance {
+            return Ok(true);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn crops_reuse_their_buffers() {
+        let frame = RgbaImage::from_fn(64, 32, |x, y| Rgba([x as u8, y as u8, 0, 255]));
+        let mut source = ReplaySource::from_frames(vec![frame.clone(), frame]).unwrap();
+        let regions = [[10, 4, 20, 8], [0, 0, 64, 32]];
+        let mut crops = Vec::new();
+
+        source.next_frame().unwrap();
+        source.capture_regions_into(&regions, &mut crops).unwrap();
+        assert_eq!(crops[0].dimensions(), (10, 4));
+        assert_eq!(crops[0].get_pixel(0, 0).0, [10, 4, 0]);
+        assert_eq!(crops[1].get_pixel(63, 31).0, [63, 31, 0]);
+        let buffer = crops[0].as_raw().as_ptr();
+
+        source.next_frame().unwrap();
+        source.capture_regions_into(&regions, &mut crops).unwrap();
+        assert_eq!(crops[0].as_raw().as_ptr(), buffer);
+        assert_eq!(crops[0].get_pixel(9, 3).0, [19, 7, 0]);
+
+        source
+            .capture_regions_into(&regions[1..], &mut crops)
+            .unwrap();
+        assert_eq!(crops.len(), 1);
+        assert_eq!(crops[0].dimensions(), (64, 32));
+    }
+
+    #[test]
+    fn crops_stop_at_the_frame_edges() {
+        let frame = RgbaImage::from_fn(16, 8, |x, y| Rgba([x as u8, y as u8, 0, 255]));
+        let mut crops = Vec::new();
+        crop_all(
+            &frame,
+            (0, 0),
+            &[[12, 4, 20, 12], [20, 0, 24, 4]],
+            &mut crops,
+        );
+        assert_eq!(crops[0].dimensions(), (4, 4));
+        assert_eq!(crops[0].get_pixel(3, 3).0, [15, 7, 0]);
+        assert_eq!(crops[1].dimensions(), (0, 4));
+
+        let mut source = ReplaySource::from_frames(vec![frame, RgbaImage::new(8, 8)]).unwrap();
+        source.next_frame().unwrap();
+        assert!(matches!(
+            source.next_frame(),
+            Err(TaskError::CaptureFailed(_))
+        ));
+    }
+
+    #[test]
+    fn union_covers_every_region() {
+        assert_eq!(
+            utils::union_region(&[[10, 40, 20, 50], [5, 60, 15, 70]]),
+            Some([5, 40, 20, 70])
+        );
+        assert_eq!(utils::union_region(&[]), None);
+    }
+
+    /// Gradient moved right by `shift` pixels and darkened to `brightness`.
+    fn animated(shift: u32, brightness: f64) -> RgbaImage {
+        RgbaImage::from_fn(64, 32, |x, y| {
+            let value = (((x + 64 - shift) % 64) * 4 + y) as f64 * brightness;
+            Rgba([value as u8, value as u8, 255 - value as u8, 255])
+        })
+    }
+
+    fn wait(frames: Vec<RgbaImage>, max_wait: Duration) -> (bool, Vec<RgbImage>, usize) {
+        let mut source = ReplaySource::from_frames(frames).unwrap();
+        source.next_frame().unwrap();
+        let mut crops = Vec::new();
+        let stable = wait_until_stable(
+            &mut source,
+            &ThreadStatus::new(),
+            &[[0, 0, 32, 32], [32, 0, 64, 32]],
+            &mut crops,
+            0.01,
+            max_wait,
+        )
+        .unwrap();
+        (stable, crops, source.frames_left())
+    }
+
+    #[test]
+    fn waits_for_fading_frames_to_settle() {
+        let frames: Vec<RgbaImage> = [0.2, 0.5, 0.8, 1.0, 1.0, 1.0, 1.0]
+            .iter()
+            .map(|brightness| animated(0, *brightness))
+            .collect();
+        let settled = animated(0, 1.0);
+        let (stable, crops, left) = wait(frames, Duration::from_secs(10));
+        assert!(stable);
+        // the first two fully faded in frames are compared
+        assert_eq!(left, 2);
+        assert_eq!(crops[1].get_pixel(0, 0).0, {
+            let pixel = settled.get_pixel(32, 0).0;
+            [pixel[0], pixel[1], pixel[2]]
+        });
+    }
+
+    #[test]
+    fn waits_for_shifting_frames_to_settle() {
+        let frames = [16, 8, 4, 2, 0, 0].map(|shift| animated(shift, 1.0));
+        let (stable, _, left) = wait(frames.to_vec(), Duration::from_secs(10));
+        assert!(stable);
+        assert_eq!(left, 0);
+    }
+
+    #[test]
+    fn gives_up_on_frames_that_keep_changing() {
+        let frames = (0..64).map(|shift| animated(shift, 1.0)).collect();
+        let (stable, _, left) = wait(frames, *constants::STABLE_FRAME_INTERVAL * 2);
+        assert!(!stable);
+        assert!(left > 0);
+    }
+}
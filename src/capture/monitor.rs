@@ -0,0 +1,84 @@
+use super::{FrameSource, crop_all};
+use crate::error::TaskError;
+use crate::utils::{self, Region, Resolution};
+use image::{RgbImage, RgbaImage};
+use std::cell::RefCell;
+use xcap::Monitor;
+
+thread_local! {
+    // xcap monitors wrap a raw display handle on Windows and cannot move
+    // between threads, so each capturing thread looks up its own
+    static MONITOR: RefCell<Option<(u32, Monitor)>> = const { RefCell::new(None) };
+}
+
+/// Captures the primary monitor, from whichever thread the task runs on.
+pub struct MonitorSource {
+    id: u32,
+    resolution: Resolution,
+}
+
+impl MonitorSource {
+    pub fn primary() -> Result<Self, TaskError> {
+        let monitor = utils::get_main_monitor()?;
+        Ok(MonitorSource {
+            id: monitor.id().map_err(capture_error)?,
+            resolution: (
+                monitor.width().map_err(capture_error)?,
+                monitor.height().map_err(capture_error)?,
+            ),
+        })
+    }
+
+    /// Runs `capture` with this thread's handle on the monitor.
+    fn with_monitor<T>(
+        &self,
+        capture: impl FnOnce(&Monitor) -> xcap::XCapResult<T>,
+    ) -> Result<T, TaskError> {
+        MONITOR.with_borrow_mut(|cached| {
+            if cached.as_ref().is_none_or(|(id, _)| *id != self.id) {
+                let monitor = Monitor::all()
+                    .map_err(capture_error)?
+                    .into_iter()
+                    .find(|monitor| monitor.id().is_ok_and(|id| id == self.id))
+                    .ok_or(TaskError::CaptureFailed(
+                        "The monitor is not connected anymore".to_string(),
+                    ))?;
+                *cached = Some((self.id, monitor));
+            }
+            let (_, monitor) = cached.as_ref().unwrap();
+            capture(monitor).map_err(capture_error)
+        })
+    }
+}
+
+fn capture_error(err: xcap::XCapError) -> TaskError {
+    TaskError::CaptureFailed(err.to_string())
+}
+
+impl FrameSource for MonitorSource {
+    fn resolution(&self) -> Resolution {
+        self.resolution
+    }
+
+    fn capture(&mut self) -> Result<RgbaImage, TaskError> {
+        self.with_monitor(|monitor| monitor.capture_image())
+    }
+
+    /// Only grabs the rectangle around `regions` rather than the whole
+    /// monitor.
+    fn capture_regions_into(
+        &mut self,
+        regions: &[Region],
+        crops: &mut Vec<RgbImage>,
+    ) -> Result<(), TaskError> {
+        let Some(union) = utils::union_region(regions) else {
+            crops.clear();
+            return Ok(());
+        };
+        let image = self.with_monitor(|monitor| {
+            monitor.capture_region(union[0], union[1], union[2] - union[0], union[3] - union[1])
+        })?;
+        crop_all(&image, (union[0], union[1]), regions, crops);
+        Ok(())
+    }
+}
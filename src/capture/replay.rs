@@ -0,0 +1,139 @@
+use super::{FrameSource, crop_all};
+use crate::error::TaskError;
+use crate::utils::{Region, Resolution};
+use image::{RgbImage, RgbaImage};
+use std::collections::VecDeque;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+enum ReplayFrame {
+    File(PathBuf),
+    Image(RgbaImage),
+}
+
+/// Serves previously recorded frames in order, moving to the next one on
+/// every `next_frame` call.
+pub struct ReplaySource {
+    frames: VecDeque<ReplayFrame>,
+    current: Option<RgbaImage>,
+    resolution: Resolution,
+}
+
+impl ReplaySource {
+    pub fn from_frames(frames: Vec<RgbaImage>) -> Result<Self, TaskError> {
+        let resolution = match frames.first() {
+            Some(frame) => frame.dimensions(),
+            None => return Err(TaskError::CaptureFailed("Replay has no frames".to_string())),
+        };
+        Ok(ReplaySource {
+            frames: frames.into_iter().map(ReplayFrame::Image).collect(),
+            current: None,
+            resolution,
+        })
+    }
+
+    pub fn from_files(paths: Vec<PathBuf>) -> Result<Self, TaskError> {
+        let resolution = match paths.first() {
+            Some(path) => image::image_dimensions(path).map_err(|err| replay_error(path, err))?,
+            None => return Err(TaskError::CaptureFailed("Replay has no frames".to_string())),
+        };
+        Ok(ReplaySource {
+            frames: paths.into_iter().map(ReplayFrame::File).collect(),
+            current: None,
+            resolution,
+        })
+    }
+
+    /// Loads every png of a folder, or a single png file. Frames named after a
+    /// timestamp (e.g. `1718000000123.png`) are played in timestamp order, the
+    /// others by name.
+    pub fn from_path(path: &Path) -> Result<Self, TaskError> {
+        if path.is_file() {
+            return Self::from_files(vec![path.to_path_buf()]);
+        }
+        let entries = fs::read_dir(path).map_err(|err| replay_error(path, err))?;
+        let mut paths: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|ext| ext.eq_ignore_ascii_case("png"))
+            })
+            .collect();
+        paths.sort_by_key(|path| {
+            let stem = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .unwrap_or_default()
+                .to_string();
+            (stem.parse::<u64>().ok(), stem)
+        });
+        Self::from_files(paths)
+    }
+}
+
+fn replay_error(path: &Path, err: impl std::fmt::Display) -> TaskError {
+    TaskError::CaptureFailed(format!("{}: {}", path.display(), err))
+}
+
+impl ReplaySource {
+    /// Frames not played yet.
+    pub fn frames_left(&self) -> usize {
+        self.frames.len()
+    }
+
+    fn current(&self) -> Result<&RgbaImage, TaskError> {
+        self.current.as_ref().ok_or(TaskError::CaptureFailed(
+            "Replay has no current frame".to_string(),
+        ))
+    }
+}
+
+impl FrameSource for ReplaySource {
+    fn resolution(&self) -> Resolution {
+        self.resolution
+    }
+
+    fn next_frame(&mut self) -> Result<(), TaskError> {
+        let frame = match self.frames.pop_front() {
+            Some(ReplayFrame::Image(image)) => image,
+            Some(ReplayFrame::File(path)) => image::open(&path)
+                .map_err(|err| replay_error(&path, err))?
+                .into_rgba8(),
+            None => {
+                return Err(TaskError::CaptureFailed(
+                    "Replay has no frames left".to_string(),
+                ));
+            }
+        };
+        // solving a frame of another size would read the wrong regions
+        if frame.dimensions() != self.resolution {
+            return Err(TaskError::CaptureFailed(format!(
+                "Replay frame is {}x{} instead of {}x{}",
+                frame.width(),
+                frame.height(),
+                self.resolution.0,
+                self.resolution.1
+            )));
+        }
+        self.current = Some(frame);
+        Ok(())
+    }
+
+    fn capture(&mut self) -> Result<RgbaImage, TaskError> {
+        self.current().cloned()
+    }
+
+    fn capture_regions_into(
+        &mut self,
+        regions: &[Region],
+        crops: &mut Vec<RgbImage>,
+    ) -> Result<(), TaskError> {
+        crop_all(self.current()?, (0, 0), regions, crops);
+        Ok(())
+    }
+
+    fn is_exhausted(&self) -> bool {
+        self.frames.is_empty()
+    }
+}
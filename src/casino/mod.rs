@@ -1,7 +1,2 @@
-use gta_assistant::constants::{R1080, R1440};
-use gta_assistant::utils::Resolution;
-
 pub mod casino;
 pub mod casino_capture;
-
-pub const SUPPORTED_RESOLUTIONS: [Resolution; 2] = [R1080, R1440];
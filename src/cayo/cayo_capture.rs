@@ -1,5 +1,8 @@
 use gta_assistant::{
-    constants,
+    TaskError, constants,
+    input::Key,
+    layout::Layouts,
+    thresholds::Thresholds,
     utils::{self, TaskData, TaskResult},
 };
 use log::info;
@@ -8,100 +11,91 @@ use std::{
     thread,
     time::Duration,
 };
-use windows::Win32::UI::Input::KeyboardAndMouse::{VK_DOWN, VK_RIGHT, VK_UP};
 
 pub fn handler(task_data: TaskData) -> TaskResult {
     thread::Builder::new()
         .name("cayo capture".to_string())
         .spawn(move || {
             info!("Thread started");
-            let resolution = utils::get_resolution();
-            if !crate::cayo::SUPPORTED_RESOLUTIONS.contains(&resolution) {
-                panic!("Cayo Capture does not support your resolution");
-            }
-
-            let header_pos = constants::CAYO_HEADER_POS.get(&resolution).unwrap();
-            let fingerprint_pos = constants::CAYO_FINGERPRINT_POS.get(&resolution).unwrap();
-            let parts_pos = constants::CAYO_PARTS_POS.get(&resolution).unwrap();
+            let mut input = task_data.input;
+            let mut frames = task_data.frames;
+            let resolution = frames.resolution();
+            let layout = Layouts::load()?.get(resolution).cayo;
+            let matcher = Thresholds::load()?.cayo.matcher.build()?;
+            let header_pos = &layout.header;
+            let fingerprint_pos = &layout.fingerprint;
+            let parts_pos = &layout.parts;
             let output_folder = constants::OUTPUT_PATH
                 .join(resolution.1.to_string())
                 .join("cayo");
 
             if !output_folder.exists() {
-                fs::create_dir_all(&output_folder).expect("failed to create output folder");
+                fs::create_dir_all(&output_folder)?;
             }
-            let monitor = utils::get_main_monitor().unwrap();
             // get current index
-            let mut curr_index = fs::read_dir(&output_folder)
-                .unwrap()
-                .map(|f| f.unwrap())
-                .filter(|f| f.file_type().unwrap().is_dir())
-                .map(|f| f.file_name().to_str().unwrap().to_string().parse::<usize>())
-                .filter(|res| res.is_ok())
-                .map(|res| res.unwrap())
-                .max()
-                .unwrap_or(0)
+            let mut curr_index = utils::numbered_folders(&output_folder)?
+                .last()
+                .map_or(0, |(index, _)| *index)
                 + 1;
-            thread::sleep(Duration::from_millis(5000));
+            task_data.thread_status.sleep(Duration::from_millis(5000));
             loop {
-                if !utils::check_thread_status(&task_data.thread_status) {
+                if !task_data.thread_status.is_running() || frames.is_exhausted() {
                     break;
                 };
+                frames.next_frame()?;
 
-                let fingerprint_screenshot =
-                    utils::capture_region(&monitor, fingerprint_pos).into_rgb8();
+                let fingerprint_screenshot = frames.capture_region(fingerprint_pos)?;
 
                 // try to find if we already saved that one
                 let mut found = false;
-                for file in fs::read_dir(&output_folder).unwrap().map(|f| f.unwrap()) {
-                    if file.file_type().unwrap().is_dir() {
-                        let fingerprint_path = file.path().join("fingerprint.png");
-                        if fingerprint_path.exists() {
-                            let prev_fingerprint_screenshot = utils::load_image(fingerprint_path);
-                            let score = utils::compare_image(
-                                &fingerprint_screenshot,
-                                &prev_fingerprint_screenshot,
-                            );
-                            println!("n°{}, score: {}", file.file_name().to_str().unwrap(), score);
-                            if score == 1f64 {
-                                // already captured this one
-                                found = true;
-                                break;
-                            }
+                for (index, path) in utils::numbered_folders(&output_folder)? {
+                    let fingerprint_path = path.join("fingerprint.png");
+                    if fingerprint_path.exists() {
+                        let prev_fingerprint_screenshot = utils::load_image(fingerprint_path)?;
+                        let score = matcher.similarity(
+                            &matcher.features(&fingerprint_screenshot),
+                            &matcher.features(&prev_fingerprint_screenshot),
+                        )?;
+                        println!("n°{}, score: {}", index, score);
+                        if score == 1f64 {
+                            // already captured this one
+                            found = true;
+                            break;
                         }
                     }
                 }
                 if !found {
                     let curr_path = output_folder.join(curr_index.to_string());
                     if !curr_path.exists() {
-                        fs::create_dir(&curr_path).expect("failed to create folder");
+                        fs::create_dir(&curr_path)?;
                     }
-                    let header_screenshot = utils::capture_region(&monitor, header_pos).into_rgb8();
+                    let header_screenshot = frames.capture_region(header_pos)?;
                     header_screenshot
                         .save(curr_path.join("header.png"))
-                        .expect("failed to screenshot header");
+                        .map_err(|err| TaskError::Io(err.to_string()))?;
 
                     fingerprint_screenshot
                         .save(curr_path.join("fingerprint.png"))
-                        .expect("failed to screenshot fingerprint");
-
-                    // save parts
+                        .map_err(|err| TaskError::Io(err.to_string()))?;
 
+                    // save parts, live sources capture the screen again for
+                    // every part while replays keep their current frame
+                    let pos = &parts_pos[0];
                     for i in 1..=8 {
-                        let pos = parts_pos.get(0).unwrap();
-                        utils::press(VK_DOWN);
-                        let part_screen = utils::capture_region(&monitor, pos);
+                        input.press(Key::Down)?;
+                        let part_screen = frames.capture_region(pos)?;
                         part_screen
                             .save(curr_path.join(i.to_string() + ".png"))
-                            .expect("failed to save part");
-                        utils::press(VK_UP);
-                        utils::press(VK_RIGHT);
+                            .map_err(|err| TaskError::Io(err.to_string()))?;
+                        input.press(Key::Up)?;
+                        input.press(Key::Right)?;
                     }
                     curr_index += 1;
                 }
-                thread::sleep(Duration::from_millis(5000));
+                task_data.thread_status.sleep(Duration::from_millis(5000));
             }
             info!("Stopping thread");
+            Ok(())
         })
         .unwrap()
 }
@@ -0,0 +1,360 @@
+use crate::{buttons, run_task};
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use gta_assistant::input::Key;
+use gta_assistant::matcher::{HashAlgorithm, MatcherConfig};
+use gta_assistant::solver::casino::{self, CasinoSolver};
+use gta_assistant::solver::cayo::{self, CayoSolver};
+use gta_assistant::solver::{SolveError, evaluation};
+use gta_assistant::thresholds::Thresholds;
+use gta_assistant::utils::{self, Resolution};
+use gta_assistant::{TaskError, ThreadStatus};
+use image::imageops::{self, FilterType};
+use log::{error, info};
+use std::path::PathBuf;
+
+/// Runs the tasks from the terminal when a command is given, opens the window
+/// otherwise.
+#[derive(Parser)]
+#[command(
+    version,
+    about = "A Software to automate solving repetitive things in GTA Online"
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Runs a task until Ctrl-C is pressed
+    Run {
+        task: RunTask,
+        /// Stop after solving one fingerprint (casino and cayo only)
+        #[arg(long)]
+        once: bool,
+    },
+    /// Saves the screenshots needed by a fingerprint solver
+    Capture { task: FingerprintTask },
+    /// Solves a full screen screenshot and prints the details, without
+    /// sending any input
+    Solve {
+        task: FingerprintTask,
+        screenshot: PathBuf,
+        /// Resolution of the game, when the screenshot was resized (e.g.
+        /// 2560x1440)
+        #[arg(long, value_parser = parse_resolution)]
+        resolution: Option<Resolution>,
+    },
+    /// Compares the image matchers on the embedded reference images, matching
+    /// the ones of each resolution against the others
+    Evaluate {
+        /// Only evaluate this task
+        task: Option<FingerprintTask>,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum RunTask {
+    Casino,
+    Cayo,
+    NoAfk,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum FingerprintTask {
+    Casino,
+    Cayo,
+}
+
+/// Runs `command` without the ui, returning the exit code of the process.
+pub fn run(command: Command) -> i32 {
+    match command {
+        Command::Run { task, once } => match task {
+            RunTask::Casino => run_button("casino", once),
+            RunTask::Cayo => run_button("cayo", once),
+            RunTask::NoAfk if once => Cli::command()
+                .error(
+                    ErrorKind::ArgumentConflict,
+                    "--once only applies to the casino and cayo tasks",
+                )
+                .exit(),
+            RunTask::NoAfk => run_button("no-afk", false),
+        },
+        Command::Capture { task } => match task {
+            FingerprintTask::Casino => run_button("casino-capture", false),
+            FingerprintTask::Cayo => run_button("cayo-capture", false),
+        },
+        Command::Solve {
+            task,
+            screenshot,
+            resolution,
+        } => match solve(task, screenshot, resolution) {
+            Ok(()) => 0,
+            Err(err) => {
+                error!("{}", err);
+                1
+            }
+        },
+        Command::Evaluate { task } => match evaluate(task) {
+            Ok(()) => 0,
+            Err(err) => {
+                error!("{}", err);
+                1
+            }
+        },
+    }
+}
+
+fn run_button(id: &str, once: bool) -> i32 {
+    let button = buttons(true)
+        .into_iter()
+        .flatten()
+        .find(|button| button.id == id)
+        .expect("every command has a button");
+
+    let thread_status = ThreadStatus::new();
+    let ctrlc_status = thread_status.clone();
+    if let Err(err) = ctrlc::set_handler(move || {
+        info!("Stopping...");
+        ctrlc_status.stop();
+    }) {
+        error!("Failed to handle Ctrl-C: {}", err);
+    }
+
+    info!("Running {}, press Ctrl-C to stop", id);
+    match run_task(&button, thread_status, once) {
+        Ok(()) => 0,
+        Err(_) => 1,
+    }
+}
+
+fn solve(
+    task: FingerprintTask,
+    screenshot: PathBuf,
+    resolution: Option<Resolution>,
+) -> Result<(), TaskError> {
+    let mut frame = utils::load_image(screenshot)?;
+    let resolution = resolution.unwrap_or(frame.dimensions());
+    if frame.dimensions() != resolution {
+        println!(
+            "Resizing the screenshot from {}x{} to {}x{}",
+            frame.width(),
+            frame.height(),
+            resolution.0,
+            resolution.1
+        );
+        frame = imageops::resize(&frame, resolution.0, resolution.1, FilterType::Triangle);
+    }
+
+    match task {
+        FingerprintTask::Casino => {
+            let solver = CasinoSolver::new(resolution)?;
+            let header = utils::crop_region(&frame, solver.header_pos());
+            print_header(
+                solver.header_similarity(&header)?,
+                solver.header_threshold(),
+            );
+            let solution = solver.solve(&frame)?;
+            print_fingerprint(solution.fingerprint_index, &solution.fingerprint_scores);
+            print_scores("part", "slot", &solution.scores);
+            println!(
+                "Selected slots: {} (total {:.3}, margin {:.3} over the next best slots)",
+                one_based(&solution.slots),
+                solution.total_score,
+                solution.margin
+            );
+            print_keys(&solution.keys);
+            print_verdict(solver.check(&solution));
+        }
+        FingerprintTask::Cayo => {
+            let solver = CayoSolver::new(resolution)?;
+            let header = utils::crop_region(&frame, solver.header_pos());
+            print_header(
+                solver.header_similarity(&header)?,
+                solver.header_threshold(),
+            );
+            let solution = solver.solve(&frame)?;
+            print_fingerprint(solution.fingerprint_index, &solution.fingerprint_scores);
+            print_scores("row", "part", &solution.scores);
+            println!("Parts shown: {}", one_based(&solution.current_indices));
+            let margins: Vec<String> = solution
+                .row_margins
+                .iter()
+                .map(|margin| format!("{:.3}", margin))
+                .collect();
+            println!("Row margins: {}", margins.join(", "));
+            print_keys(&solution.keys);
+            print_verdict(solver.check(&solution));
+        }
+    }
+    Ok(())
+}
+
+/// Matchers compared by `evaluate`, on top of the configured ones.
+fn matcher_candidates() -> Vec<MatcherConfig> {
+    let mut candidates = Vec::new();
+    for algorithm in [
+        HashAlgorithm::Mean,
+        HashAlgorithm::Median,
+        HashAlgorithm::Gradient,
+        HashAlgorithm::VertGradient,
+        HashAlgorithm::DoubleGradient,
+        HashAlgorithm::Blockhash,
+    ] {
+        for size in [8, 16] {
+            candidates.push(MatcherConfig::Hash {
+                algorithm,
+                size,
+                dct: false,
+            });
+        }
+    }
+    candidates.push(MatcherConfig::Hash {
+        algorithm: HashAlgorithm::Mean,
+        size: 8,
+        dct: true,
+    });
+    for size in [16, 32] {
+        candidates.push(MatcherConfig::Ncc { size });
+        candidates.push(MatcherConfig::Ssim { size });
+        candidates.push(MatcherConfig::Mse { size });
+    }
+    candidates
+}
+
+fn evaluate(task: Option<FingerprintTask>) -> Result<(), TaskError> {
+    let thresholds = Thresholds::load()?;
+    let tasks = match task {
+        Some(FingerprintTask::Casino) => vec![(casino::assets(), thresholds.casino)],
+        Some(FingerprintTask::Cayo) => vec![(cayo::assets(), thresholds.cayo)],
+        None => vec![
+            (casino::assets(), thresholds.casino),
+            (cayo::assets(), thresholds.cayo),
+        ],
+    };
+    for (assets, thresholds) in tasks {
+        let mut candidates = vec![thresholds.matcher];
+        candidates.extend(
+            matcher_candidates()
+                .into_iter()
+                .filter(|candidate| *candidate != thresholds.matcher),
+        );
+        println!("{}", assets.name);
+        println!(
+            "  {:24} {:>12} {:>12} {:>12} {:>10}",
+            "matcher", "fingerprints", "parts", "worst margin", "per image"
+        );
+        for candidate in candidates {
+            let evaluation = evaluation::evaluate(&candidate, &assets)?;
+            let configured = if candidate == thresholds.matcher {
+                " (configured)"
+            } else {
+                ""
+            };
+            println!(
+                "  {:24} {:>11.1}% {:>11.1}% {:>12.3} {:>8.2}ms{}",
+                candidate.to_string(),
+                evaluation.fingerprint_accuracy() * 100.0,
+                evaluation.part_accuracy() * 100.0,
+                evaluation.worst_margin,
+                evaluation.time_per_image.as_secs_f64() * 1000.0,
+                configured
+            );
+        }
+    }
+    Ok(())
+}
+
+fn print_header(similarity: f64, threshold: f64) {
+    let detected = if similarity > threshold {
+        "detected"
+    } else {
+        "NOT detected"
+    };
+    println!(
+        "Header similarity: {:.3} ({}, threshold {})",
+        similarity, detected, threshold
+    );
+}
+
+fn print_fingerprint(index: usize, scores: &[f64]) {
+    println!("Fingerprint: {}", index + 1);
+    for (i, score) in scores.iter().enumerate() {
+        println!("  fingerprint {}: {:.3}", i + 1, score);
+    }
+}
+
+/// Prints a similarity table, one line per `row_name` and one column per
+/// `column_name`, numbered from 1.
+fn print_scores(row_name: &str, column_name: &str, scores: &[Vec<f64>]) {
+    let columns = scores.first().map_or(0, Vec::len);
+    print!("{:8}", "");
+    for column in 1..=columns {
+        print!(" {:>7}", format!("{} {}", column_name, column));
+    }
+    println!();
+    for (row, row_scores) in scores.iter().enumerate() {
+        print!("{:8}", format!("{} {}", row_name, row + 1));
+        for score in row_scores {
+            print!(" {:>7.3}", score);
+        }
+        println!();
+    }
+}
+
+fn print_verdict(check: Result<(), SolveError>) {
+    match check {
+        Ok(()) => println!("Confident enough to send the keys"),
+        Err(err) => println!("{}, no input would be sent", err),
+    }
+}
+
+fn print_keys(keys: &[Key]) {
+    let keys: Vec<String> = keys.iter().map(|key| format!("{:?}", key)).collect();
+    println!("Keys: {}", keys.join(" "));
+}
+
+fn one_based(indices: &[usize]) -> String {
+    let indices: Vec<String> = indices.iter().map(|i| (i + 1).to_string()).collect();
+    indices.join(", ")
+}
+
+fn parse_resolution(s: &str) -> Result<Resolution, String> {
+    let invalid = || format!("expected WIDTHxHEIGHT, got {:?}", s);
+    let (width, height) = s.split_once('x').ok_or_else(invalid)?;
+    let width = width.trim().parse().map_err(|_| invalid())?;
+    let height = height.trim().parse().map_err(|_| invalid())?;
+    // an empty screen has no UI area to scale the layouts to
+    if width == 0 || height == 0 {
+        return Err(invalid());
+    }
+    Ok((width, height))
+}
+
+/// Release builds use the windows subsystem and start without a console,
+/// attach to the one of the terminal the command was typed in.
+#[cfg(target_os = "windows")]
+pub fn attach_console() {
+    use windows::Win32::System::Console::{ATTACH_PARENT_PROCESS, AttachConsole};
+    unsafe {
+        let _ = AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+pub fn attach_console() {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolutions_need_a_width_and_a_height() {
+        assert_eq!(parse_resolution("2560x1440"), Ok((2560, 1440)));
+        assert_eq!(parse_resolution("1920 x 1080"), Ok((1920, 1080)));
+        for invalid in ["0x0", "0x1080", "1920x0", "1920", "ax1080", "-1x1080"] {
+            assert!(parse_resolution(invalid).is_err(), "{}", invalid);
+        }
+    }
+}
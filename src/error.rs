@@ -0,0 +1,52 @@
+use crate::solver::SolveError;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum TaskError {
+    CaptureFailed(String),
+    AssetMissing(String),
+    AssetDecode(String),
+    Layout(String),
+    Thresholds(String),
+    Screens(String),
+    Matcher(String),
+    InputFailed(String),
+    Hotkey(String),
+    Io(String),
+    Solve(SolveError),
+    /// The task was stopped while it was running.
+    Cancelled,
+}
+
+impl fmt::Display for TaskError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TaskError::CaptureFailed(err) => write!(f, "Failed to capture the screen: {}", err),
+            TaskError::AssetMissing(path) => write!(f, "Missing image: {}", path),
+            TaskError::AssetDecode(err) => write!(f, "Failed to decode image: {}", err),
+            TaskError::Layout(err) => write!(f, "Invalid layout: {}", err),
+            TaskError::Thresholds(err) => write!(f, "Invalid thresholds: {}", err),
+            TaskError::Screens(err) => write!(f, "Invalid screens: {}", err),
+            TaskError::Matcher(err) => write!(f, "Failed to compare images: {}", err),
+            TaskError::InputFailed(err) => write!(f, "Failed to send keyboard input: {}", err),
+            TaskError::Hotkey(err) => write!(f, "Failed to set up hotkeys: {}", err),
+            TaskError::Io(err) => write!(f, "Failed to write files: {}", err),
+            TaskError::Solve(err) => write!(f, "Failed to solve: {}", err),
+            TaskError::Cancelled => write!(f, "The task was stopped"),
+        }
+    }
+}
+
+impl std::error::Error for TaskError {}
+
+impl From<SolveError> for TaskError {
+    fn from(err: SolveError) -> Self {
+        TaskError::Solve(err)
+    }
+}
+
+impl From<std::io::Error> for TaskError {
+    fn from(err: std::io::Error) -> Self {
+        TaskError::Io(err.to_string())
+    }
+}
@@ -0,0 +1,122 @@
+use super::{Hotkey, HotkeyCode, Modifiers};
+use crate::error::TaskError;
+use crate::input::linux::LETTERS;
+use evdev::{Device, EventSummary, KeyCode};
+use log::error;
+use std::sync::Arc;
+use std::thread;
+
+const DIGITS: [KeyCode; 10] = [
+    KeyCode::KEY_0,
+    KeyCode::KEY_1,
+    KeyCode::KEY_2,
+    KeyCode::KEY_3,
+    KeyCode::KEY_4,
+    KeyCode::KEY_5,
+    KeyCode::KEY_6,
+    KeyCode::KEY_7,
+    KeyCode::KEY_8,
+    KeyCode::KEY_9,
+];
+
+const FUNCTION_KEYS: [KeyCode; 12] = [
+    KeyCode::KEY_F1,
+    KeyCode::KEY_F2,
+    KeyCode::KEY_F3,
+    KeyCode::KEY_F4,
+    KeyCode::KEY_F5,
+    KeyCode::KEY_F6,
+    KeyCode::KEY_F7,
+    KeyCode::KEY_F8,
+    KeyCode::KEY_F9,
+    KeyCode::KEY_F10,
+    KeyCode::KEY_F11,
+    KeyCode::KEY_F12,
+];
+
+fn key_code(code: HotkeyCode) -> KeyCode {
+    match code {
+        HotkeyCode::F(n) => FUNCTION_KEYS[n as usize - 1],
+        HotkeyCode::Char(c) if c.is_ascii_digit() => DIGITS[(c as u8 - b'0') as usize],
+        HotkeyCode::Char(c) => LETTERS[(c as u8 - b'a') as usize],
+    }
+}
+
+/// Reads every keyboard from `/dev/input` rather than grabbing keys from the
+/// display server, so it works under X11 and Wayland alike. Needs read access
+/// to the devices (usually by being in the `input` group).
+pub fn listen<F>(hotkeys: Vec<Hotkey>, on_press: F) -> Result<(), TaskError>
+where
+    F: Fn(usize) + Send + Sync + 'static,
+{
+    let keyboards: Vec<Device> = evdev::enumerate()
+        .map(|(_, device)| device)
+        // skip the virtual keyboard used to send inputs
+        .filter(|device| device.name() != Some("gta-assistant"))
+        .filter(|device| {
+            device
+                .supported_keys()
+                .is_some_and(|keys| keys.contains(KeyCode::KEY_ENTER))
+        })
+        .collect();
+    if keyboards.is_empty() {
+        return Err(TaskError::Hotkey(
+            "No readable keyboard in /dev/input, check that your user is in the input group"
+                .to_string(),
+        ));
+    }
+
+    let hotkeys: Arc<Vec<(Modifiers, KeyCode)>> = Arc::new(
+        hotkeys
+            .iter()
+            .map(|hotkey| (hotkey.modifiers, key_code(hotkey.code)))
+            .collect(),
+    );
+    let on_press = Arc::new(on_press);
+    for mut device in keyboards {
+        let hotkeys = hotkeys.clone();
+        let on_press = on_press.clone();
+        thread::Builder::new()
+            .name("hotkeys".to_string())
+            .spawn(move || {
+                let name = device.name().unwrap_or("keyboard").to_string();
+                let mut modifiers = Modifiers::default();
+                loop {
+                    let events = match device.fetch_events() {
+                        Ok(events) => events,
+                        Err(err) => {
+                            error!("Stopped reading hotkeys from {}: {}", name, err);
+                            return;
+                        }
+                    };
+                    for event in events {
+                        let EventSummary::Key(_, code, value) = event.destructure() else {
+                            continue;
+                        };
+                        // value is 0 on release, 1 on press and 2 on repeat
+                        match code {
+                            KeyCode::KEY_LEFTCTRL | KeyCode::KEY_RIGHTCTRL => {
+                                modifiers.ctrl = value != 0
+                            }
+                            KeyCode::KEY_LEFTALT | KeyCode::KEY_RIGHTALT => {
+                                modifiers.alt = value != 0
+                            }
+                            KeyCode::KEY_LEFTSHIFT | KeyCode::KEY_RIGHTSHIFT => {
+                                modifiers.shift = value != 0
+                            }
+                            _ if value == 1 => {
+                                if let Some(index) = hotkeys
+                                    .iter()
+                                    .position(|hotkey| *hotkey == (modifiers, code))
+                                {
+                                    on_press(index);
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+            })?;
+    }
+    Ok(())
+}
@@ -0,0 +1,215 @@
+use crate::error::TaskError;
+use crate::utils::{self, Asset};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::str::FromStr;
+
+#[cfg(target_os = "linux")]
+mod linux;
+#[cfg(target_os = "windows")]
+mod windows;
+
+const EMBEDDED_HOTKEYS: &str = "assets/hotkeys.toml";
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum HotkeyCode {
+    /// Function key, from 1 to 12.
+    F(u8),
+    /// Ascii letter (lowercase) or digit.
+    Char(char),
+}
+
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq)]
+pub struct Modifiers {
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+}
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct Hotkey {
+    pub modifiers: Modifiers,
+    pub code: HotkeyCode,
+}
+
+impl FromStr for Hotkey {
+    type Err = TaskError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || TaskError::Hotkey(format!("invalid hotkey {:?}", s));
+        let mut modifiers = Modifiers::default();
+        let mut parts: Vec<&str> = s.split('+').map(str::trim).collect();
+        let key = parts.pop().ok_or_else(invalid)?;
+        for modifier in parts {
+            match modifier.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => modifiers.ctrl = true,
+                "alt" => modifiers.alt = true,
+                "shift" => modifiers.shift = true,
+                _ => return Err(invalid()),
+            }
+        }
+
+        let code = match key.to_ascii_lowercase().as_str() {
+            key if key.len() == 1 && key.chars().all(|c| c.is_ascii_alphanumeric()) => {
+                HotkeyCode::Char(key.chars().next().unwrap())
+            }
+            key => match key.strip_prefix('f').map(str::parse::<u8>) {
+                Some(Ok(n)) if (1..=12).contains(&n) => HotkeyCode::F(n),
+                _ => return Err(invalid()),
+            },
+        };
+        Ok(Hotkey { modifiers, code })
+    }
+}
+
+impl fmt::Display for Hotkey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.modifiers.ctrl {
+            write!(f, "Ctrl+")?;
+        }
+        if self.modifiers.alt {
+            write!(f, "Alt+")?;
+        }
+        if self.modifiers.shift {
+            write!(f, "Shift+")?;
+        }
+        match self.code {
+            HotkeyCode::F(n) => write!(f, "F{}", n),
+            HotkeyCode::Char(c) => write!(f, "{}", c.to_ascii_uppercase()),
+        }
+    }
+}
+
+/// Hotkey of every task id, from the embedded `hotkeys.toml` with the user's
+/// one merged over it. Ids missing from `task_ids` and hotkeys bound to two
+/// tasks are refused.
+pub fn load_bindings(task_ids: &[&str]) -> Result<HashMap<String, Hotkey>, TaskError> {
+    let file = Asset::get(EMBEDDED_HOTKEYS)
+        .ok_or(TaskError::AssetMissing(EMBEDDED_HOTKEYS.to_string()))?;
+    let mut bindings = parse(&String::from_utf8_lossy(&file.data))?;
+    if let Some(path) = utils::config_path("hotkeys.toml") {
+        if path.exists() {
+            let overrides = parse(&fs::read_to_string(&path)?)
+                .map_err(|err| TaskError::Hotkey(format!("{}: {}", path.display(), err)))?;
+            bindings.extend(overrides);
+        }
+    }
+    resolve(bindings, task_ids)
+}
+
+fn resolve(
+    bindings: HashMap<String, String>,
+    task_ids: &[&str],
+) -> Result<HashMap<String, Hotkey>, TaskError> {
+    let mut tasks: HashMap<Hotkey, String> = HashMap::new();
+    let mut resolved = HashMap::new();
+    for (id, hotkey) in bindings {
+        if !task_ids.contains(&id.as_str()) {
+            return Err(TaskError::Hotkey(format!(
+                "unknown task {:?}, expected one of {}",
+                id,
+                task_ids.join(", ")
+            )));
+        }
+        if hotkey.is_empty() {
+            continue;
+        }
+        let hotkey: Hotkey = hotkey.parse()?;
+        if let Some(other) = tasks.insert(hotkey, id.clone()) {
+            return Err(TaskError::Hotkey(format!(
+                "{} is bound to both {} and {}",
+                hotkey, other, id
+            )));
+        }
+        resolved.insert(id, hotkey);
+    }
+    Ok(resolved)
+}
+
+fn parse(source: &str) -> Result<HashMap<String, String>, TaskError> {
+    toml::from_str(source).map_err(|err| TaskError::Hotkey(err.to_string()))
+}
+
+/// Listens for `hotkeys` in the background, calling `on_press` with the index
+/// of the one pressed, whichever window has the focus.
+pub fn listen<F>(hotkeys: Vec<Hotkey>, on_press: F) -> Result<(), TaskError>
+where
+    F: Fn(usize) + Send + Sync + 'static,
+{
+    if hotkeys.is_empty() {
+        return Ok(());
+    }
+    #[cfg(target_os = "windows")]
+    {
+        windows::listen(hotkeys, on_press)
+    }
+    #[cfg(target_os = "linux")]
+    {
+        linux::listen(hotkeys, on_press)
+    }
+    #[cfg(not(any(target_os = "windows", target_os = "linux")))]
+    {
+        let _ = on_press;
+        Err(TaskError::Hotkey(
+            "Global hotkeys are not available on this platform".to_string(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_displays_hotkeys() {
+        let hotkey: Hotkey = "ctrl + shift+f9".parse().unwrap();
+        assert_eq!(
+            hotkey,
+            Hotkey {
+                modifiers: Modifiers {
+                    ctrl: true,
+                    alt: false,
+                    shift: true,
+                },
+                code: HotkeyCode::F(9),
+            }
+        );
+        assert_eq!(hotkey.to_string(), "Ctrl+Shift+F9");
+        assert_eq!(
+            "Alt+z".parse::<Hotkey>().unwrap().code,
+            HotkeyCode::Char('z')
+        );
+        for invalid in ["", "F13", "Super+F1", "Ctrl+", "Enter"] {
+            assert!(invalid.parse::<Hotkey>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn embedded_bindings_are_valid() {
+        let bindings = parse(&String::from_utf8_lossy(
+            &Asset::get(EMBEDDED_HOTKEYS).unwrap().data,
+        ))
+        .unwrap();
+        for hotkey in bindings.values() {
+            hotkey.parse::<Hotkey>().unwrap();
+        }
+        assert_eq!(bindings["casino"], "F9");
+    }
+
+    #[test]
+    fn refuses_unknown_tasks_and_shared_hotkeys() {
+        let task_ids = ["casino", "cayo", "no-afk"];
+        let bindings = |source: &str| resolve(parse(source).unwrap(), &task_ids);
+        let resolved = bindings("casino = \"F9\"\ncayo = \"\"\nno-afk = \"ctrl+F9\"").unwrap();
+        assert_eq!(resolved.len(), 2);
+        assert!(matches!(
+            bindings("casnio = \"F9\""),
+            Err(TaskError::Hotkey(_))
+        ));
+        assert!(matches!(
+            bindings("casino = \"F9\"\ncayo = \"f9\""),
+            Err(TaskError::Hotkey(_))
+        ));
+    }
+}
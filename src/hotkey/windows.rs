@@ -0,0 +1,66 @@
+use super::{Hotkey, HotkeyCode};
+use crate::error::TaskError;
+use ::windows::Win32::UI::Input::KeyboardAndMouse::{
+    HOT_KEY_MODIFIERS, MOD_ALT, MOD_CONTROL, MOD_NOREPEAT, MOD_SHIFT, RegisterHotKey, VK_F1,
+};
+use ::windows::Win32::UI::WindowsAndMessaging::{GetMessageW, MSG, WM_HOTKEY};
+use std::sync::mpsc;
+use std::thread;
+
+fn modifiers(hotkey: &Hotkey) -> HOT_KEY_MODIFIERS {
+    let mut modifiers = MOD_NOREPEAT;
+    if hotkey.modifiers.ctrl {
+        modifiers |= MOD_CONTROL;
+    }
+    if hotkey.modifiers.alt {
+        modifiers |= MOD_ALT;
+    }
+    if hotkey.modifiers.shift {
+        modifiers |= MOD_SHIFT;
+    }
+    modifiers
+}
+
+fn virtual_key(code: HotkeyCode) -> u32 {
+    match code {
+        HotkeyCode::F(n) => (VK_F1.0 + n as u16 - 1) as u32,
+        // letter and digit virtual keys are their uppercase ascii code
+        HotkeyCode::Char(c) => c.to_ascii_uppercase() as u32,
+    }
+}
+
+pub fn listen<F>(hotkeys: Vec<Hotkey>, on_press: F) -> Result<(), TaskError>
+where
+    F: Fn(usize) + Send + Sync + 'static,
+{
+    let (registered_sender, registered) = mpsc::channel();
+    thread::Builder::new()
+        .name("hotkeys".to_string())
+        .spawn(move || {
+            // hotkeys are posted to the message queue of the thread that
+            // registered them
+            for (id, hotkey) in hotkeys.iter().enumerate() {
+                let result = unsafe {
+                    RegisterHotKey(None, id as i32, modifiers(hotkey), virtual_key(hotkey.code))
+                };
+                if let Err(err) = result {
+                    let _ = registered_sender.send(Err(TaskError::Hotkey(format!(
+                        "{} is already in use ({})",
+                        hotkey, err
+                    ))));
+                    return;
+                }
+            }
+            let _ = registered_sender.send(Ok(()));
+
+            let mut msg = MSG::default();
+            while unsafe { GetMessageW(&mut msg, None, 0, 0) }.as_bool() {
+                if msg.message == WM_HOTKEY {
+                    on_press(msg.wParam.0);
+                }
+            }
+        })?;
+    registered
+        .recv()
+        .map_err(|_| TaskError::Hotkey("The hotkey thread stopped".to_string()))?
+}
@@ -0,0 +1,100 @@
+use super::{InputBackend, Key};
+use crate::error::TaskError;
+use crate::utils::ThreadStatus;
+use std::time::Duration;
+
+/// Backend that stops typing as soon as its task is stopped, checking before
+/// every key press so that a sequence in progress is cut short.
+pub struct CancellableBackend {
+    inner: Box<dyn InputBackend>,
+    thread_status: ThreadStatus,
+}
+
+impl CancellableBackend {
+    pub fn new(inner: Box<dyn InputBackend>, thread_status: ThreadStatus) -> Self {
+        CancellableBackend {
+            inner,
+            thread_status,
+        }
+    }
+
+    fn check(&self) -> Result<(), TaskError> {
+        if self.thread_status.is_running() {
+            Ok(())
+        } else {
+            Err(TaskError::Cancelled)
+        }
+    }
+}
+
+impl InputBackend for CancellableBackend {
+    fn key_down(&mut self, key: Key) -> Result<(), TaskError> {
+        self.check()?;
+        self.inner.key_down(key)
+    }
+
+    fn key_up(&mut self, key: Key) -> Result<(), TaskError> {
+        // released even when stopped, so no key stays down
+        self.inner.key_up(key)
+    }
+
+    fn press_for(&mut self, key: Key, duration: Duration) -> Result<(), TaskError> {
+        self.check()?;
+        self.inner.press_for(key, duration)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Counts key presses and stops its task after `stop_after` of them.
+    struct StoppingBackend {
+        presses: Arc<AtomicUsize>,
+        thread_status: ThreadStatus,
+        stop_after: usize,
+    }
+
+    impl InputBackend for StoppingBackend {
+        fn key_down(&mut self, _key: Key) -> Result<(), TaskError> {
+            Ok(())
+        }
+
+        fn key_up(&mut self, _key: Key) -> Result<(), TaskError> {
+            if self.presses.fetch_add(1, Ordering::SeqCst) + 1 == self.stop_after {
+                self.thread_status.stop();
+            }
+            Ok(())
+        }
+
+        fn press_for(&mut self, key: Key, _duration: Duration) -> Result<(), TaskError> {
+            self.key_down(key)?;
+            self.key_up(key)
+        }
+    }
+
+    #[test]
+    fn stops_between_key_presses() {
+        let thread_status = ThreadStatus::new();
+        let presses = Arc::new(AtomicUsize::new(0));
+        let mut input = CancellableBackend::new(
+            Box::new(StoppingBackend {
+                presses: presses.clone(),
+                thread_status: thread_status.clone(),
+                stop_after: 3,
+            }),
+            thread_status,
+        );
+        assert!(matches!(
+            input.multiple_press(Key::Right, 10),
+            Err(TaskError::Cancelled)
+        ));
+        assert!(matches!(
+            input.sequence(&[Key::Up, Key::Down]),
+            Err(TaskError::Cancelled)
+        ));
+        assert_eq!(presses.load(Ordering::SeqCst), 3);
+    }
+}
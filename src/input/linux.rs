@@ -0,0 +1,100 @@
+use super::{InputBackend, Key};
+use crate::error::TaskError;
+use evdev::{AttributeSet, KeyCode, uinput::VirtualDevice};
+
+pub(crate) const LETTERS: [KeyCode; 26] = [
+    KeyCode::KEY_A,
+    KeyCode::KEY_B,
+    KeyCode::KEY_C,
+    KeyCode::KEY_D,
+    KeyCode::KEY_E,
+    KeyCode::KEY_F,
+    KeyCode::KEY_G,
+    KeyCode::KEY_H,
+    KeyCode::KEY_I,
+    KeyCode::KEY_J,
+    KeyCode::KEY_K,
+    KeyCode::KEY_L,
+    KeyCode::KEY_M,
+    KeyCode::KEY_N,
+    KeyCode::KEY_O,
+    KeyCode::KEY_P,
+    KeyCode::KEY_Q,
+    KeyCode::KEY_R,
+    KeyCode::KEY_S,
+    KeyCode::KEY_T,
+    KeyCode::KEY_U,
+    KeyCode::KEY_V,
+    KeyCode::KEY_W,
+    KeyCode::KEY_X,
+    KeyCode::KEY_Y,
+    KeyCode::KEY_Z,
+];
+
+/// Sends keys through a virtual uinput keyboard, which works under X11,
+/// Wayland and Proton alike. Needs write access to `/dev/uinput`.
+pub struct UinputBackend {
+    device: VirtualDevice,
+}
+
+impl UinputBackend {
+    pub fn new() -> Result<Self, TaskError> {
+        let mut keys = AttributeSet::<KeyCode>::new();
+        for code in [
+            KeyCode::KEY_UP,
+            KeyCode::KEY_DOWN,
+            KeyCode::KEY_LEFT,
+            KeyCode::KEY_RIGHT,
+            KeyCode::KEY_ENTER,
+            KeyCode::KEY_TAB,
+        ]
+        .into_iter()
+        .chain(LETTERS)
+        {
+            keys.insert(code);
+        }
+
+        let device = VirtualDevice::builder()
+            .and_then(|builder| builder.name("gta-assistant").with_keys(&keys))
+            .and_then(|builder| builder.build())
+            .map_err(|err| {
+                TaskError::InputFailed(format!("Failed to create uinput device: {}", err))
+            })?;
+        Ok(UinputBackend { device })
+    }
+
+    fn send(&mut self, key: Key, value: i32) -> Result<(), TaskError> {
+        let code = key_code(key)?;
+        self.device
+            .emit(&[*evdev::KeyEvent::new(code, value)])
+            .map_err(|err| TaskError::InputFailed(format!("Failed to send {:?}: {}", key, err)))
+    }
+}
+
+fn key_code(key: Key) -> Result<KeyCode, TaskError> {
+    let code = match key {
+        Key::Up => KeyCode::KEY_UP,
+        Key::Down => KeyCode::KEY_DOWN,
+        Key::Left => KeyCode::KEY_LEFT,
+        Key::Right => KeyCode::KEY_RIGHT,
+        Key::Enter => KeyCode::KEY_ENTER,
+        Key::Tab => KeyCode::KEY_TAB,
+        Key::Letter(c) if c.is_ascii_alphabetic() => {
+            LETTERS[(c.to_ascii_lowercase() as u8 - b'a') as usize]
+        }
+        Key::Letter(c) => {
+            return Err(TaskError::InputFailed(format!("Unsupported key: {:?}", c)));
+        }
+    };
+    Ok(code)
+}
+
+impl InputBackend for UinputBackend {
+    fn key_down(&mut self, key: Key) -> Result<(), TaskError> {
+        self.send(key, 1)
+    }
+
+    fn key_up(&mut self, key: Key) -> Result<(), TaskError> {
+        self.send(key, 0)
+    }
+}
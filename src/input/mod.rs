@@ -0,0 +1,77 @@
+use crate::constants;
+use crate::error::TaskError;
+use std::{thread, time::Duration};
+
+mod cancellable;
+#[cfg(target_os = "linux")]
+pub(crate) mod linux;
+mod recording;
+#[cfg(target_os = "windows")]
+mod windows;
+
+pub use cancellable::CancellableBackend;
+#[cfg(target_os = "linux")]
+pub use linux::UinputBackend;
+pub use recording::{KeyEvent, RecordingBackend};
+#[cfg(target_os = "windows")]
+pub use windows::SendInputBackend;
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum Key {
+    Up,
+    Down,
+    Left,
+    Right,
+    Enter,
+    Tab,
+    Letter(char),
+}
+
+pub trait InputBackend: Send {
+    fn key_down(&mut self, key: Key) -> Result<(), TaskError>;
+    fn key_up(&mut self, key: Key) -> Result<(), TaskError>;
+
+    fn press_for(&mut self, key: Key, duration: Duration) -> Result<(), TaskError> {
+        self.key_down(key)?;
+        thread::sleep(duration);
+        self.key_up(key)?;
+        thread::sleep(duration);
+        Ok(())
+    }
+
+    fn press(&mut self, key: Key) -> Result<(), TaskError> {
+        self.press_for(key, *constants::PRESS_DURATION)
+    }
+
+    fn multiple_press(&mut self, key: Key, count: usize) -> Result<(), TaskError> {
+        for _ in 0..count {
+            self.press(key)?;
+        }
+        Ok(())
+    }
+
+    fn sequence(&mut self, keys: &[Key]) -> Result<(), TaskError> {
+        for key in keys {
+            self.press(*key)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the input backend for the current platform.
+pub fn default_backend() -> Result<Box<dyn InputBackend>, TaskError> {
+    #[cfg(target_os = "windows")]
+    {
+        Ok(Box::new(SendInputBackend))
+    }
+    #[cfg(target_os = "linux")]
+    {
+        Ok(Box::new(UinputBackend::new()?))
+    }
+    #[cfg(not(any(target_os = "windows", target_os = "linux")))]
+    {
+        Err(TaskError::InputFailed(
+            "No input backend available for this platform".to_string(),
+        ))
+    }
+}
@@ -0,0 +1,50 @@
+use super::{InputBackend, Key};
+use crate::error::TaskError;
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyEvent {
+    Down(Key),
+    Up(Key),
+}
+
+/// Backend that keeps every event in memory instead of sending it, used to
+/// check what a task would have typed.
+#[derive(Default)]
+pub struct RecordingBackend {
+    pub events: Vec<KeyEvent>,
+}
+
+impl RecordingBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keys that were fully pressed (down then up), in order.
+    pub fn pressed_keys(&self) -> Vec<Key> {
+        self.events
+            .iter()
+            .filter_map(|event| match event {
+                KeyEvent::Up(key) => Some(*key),
+                KeyEvent::Down(_) => None,
+            })
+            .collect()
+    }
+}
+
+impl InputBackend for RecordingBackend {
+    fn key_down(&mut self, key: Key) -> Result<(), TaskError> {
+        self.events.push(KeyEvent::Down(key));
+        Ok(())
+    }
+
+    fn key_up(&mut self, key: Key) -> Result<(), TaskError> {
+        self.events.push(KeyEvent::Up(key));
+        Ok(())
+    }
+
+    fn press_for(&mut self, key: Key, _duration: Duration) -> Result<(), TaskError> {
+        self.key_down(key)?;
+        self.key_up(key)
+    }
+}
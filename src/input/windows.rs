@@ -0,0 +1,82 @@
+use super::{InputBackend, Key};
+use crate::error::TaskError;
+use ::windows::Win32::UI::Input::KeyboardAndMouse::{
+    INPUT, INPUT_0, INPUT_KEYBOARD, KEYBD_EVENT_FLAGS, KEYBDINPUT, KEYEVENTF_EXTENDEDKEY,
+    KEYEVENTF_KEYUP, MAPVK_VK_TO_VSC, MapVirtualKeyA, SendInput, VIRTUAL_KEY, VK_DELETE, VK_DOWN,
+    VK_END, VK_HOME, VK_INSERT, VK_LEFT, VK_NEXT, VK_PAUSE, VK_PRIOR, VK_RCONTROL, VK_RETURN,
+    VK_RIGHT, VK_TAB, VK_UP,
+};
+
+const EXTENDED_KEYS: [VIRTUAL_KEY; 12] = [
+    VK_RCONTROL,
+    VK_PAUSE,
+    VK_HOME,
+    VK_PRIOR,
+    VK_UP,
+    VK_LEFT,
+    VK_DOWN,
+    VK_RIGHT,
+    VK_NEXT,
+    VK_END,
+    VK_INSERT,
+    VK_DELETE,
+];
+
+pub struct SendInputBackend;
+
+fn virtual_key(key: Key) -> VIRTUAL_KEY {
+    match key {
+        Key::Up => VK_UP,
+        Key::Down => VK_DOWN,
+        Key::Left => VK_LEFT,
+        Key::Right => VK_RIGHT,
+        Key::Enter => VK_RETURN,
+        Key::Tab => VK_TAB,
+        // letter virtual keys are their uppercase ascii code
+        Key::Letter(c) => VIRTUAL_KEY(c.to_ascii_uppercase() as u16),
+    }
+}
+
+fn send(key: Key, down: bool) -> Result<(), TaskError> {
+    let vk_code = virtual_key(key);
+    let mut flags = if down {
+        KEYBD_EVENT_FLAGS(0)
+    } else {
+        KEYEVENTF_KEYUP
+    };
+    if EXTENDED_KEYS.contains(&vk_code) {
+        flags |= KEYEVENTF_EXTENDEDKEY;
+    }
+    let scan = unsafe { MapVirtualKeyA(vk_code.0 as u32, MAPVK_VK_TO_VSC) };
+
+    let input = INPUT {
+        r#type: INPUT_KEYBOARD,
+        Anonymous: INPUT_0 {
+            ki: KEYBDINPUT {
+                wVk: VIRTUAL_KEY(0),
+                wScan: scan as u16,
+                dwFlags: flags,
+                time: 0,
+                dwExtraInfo: 0,
+            },
+        },
+    };
+    let sent = unsafe { SendInput(&[input], size_of::<INPUT>() as i32) };
+    if sent != 1 {
+        return Err(TaskError::InputFailed(format!(
+            "SendInput failed for {:?}",
+            key
+        )));
+    }
+    Ok(())
+}
+
+impl InputBackend for SendInputBackend {
+    fn key_down(&mut self, key: Key) -> Result<(), TaskError> {
+        send(key, true)
+    }
+
+    fn key_up(&mut self, key: Key) -> Result<(), TaskError> {
+        send(key, false)
+    }
+}
@@ -0,0 +1,382 @@
+use crate::constants::R1080;
+use crate::error::TaskError;
+use crate::solver::cayo::ROW_COUNT;
+use crate::utils::{self, Asset, Region, Resolution};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+
+const EMBEDDED_LAYOUTS: &str = "assets/layouts.toml";
+const CASINO_SLOT_COUNT: usize = 8;
+/// Resolution every other layout is derived from when it is not configured.
+pub const REFERENCE_RESOLUTION: Resolution = R1080;
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TaskLayout {
+    pub header: Region,
+    pub fingerprint: Region,
+    pub parts: Vec<Region>,
+    /// Where the game tells whether a submitted fingerprint was right, only
+    /// needed to recognize the result screens.
+    #[serde(default)]
+    pub result: Option<Region>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layout {
+    pub casino: TaskLayout,
+    pub cayo: TaskLayout,
+}
+
+/// Screen regions of every known resolution.
+#[derive(Debug, Clone)]
+pub struct Layouts {
+    layouts: HashMap<Resolution, Layout>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LayoutFile {
+    #[serde(default)]
+    resolution: Vec<LayoutEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LayoutEntry {
+    width: u32,
+    height: u32,
+    scale_from: Option<Resolution>,
+    casino: Option<TaskLayout>,
+    cayo: Option<TaskLayout>,
+}
+
+impl Layouts {
+    /// Layouts shipped with the executable.
+    pub fn embedded() -> Result<Self, TaskError> {
+        Self::resolve(parse(&embedded_source()?)?)
+    }
+
+    /// Embedded layouts with the user's `layouts.toml` merged over them.
+    pub fn load() -> Result<Self, TaskError> {
+        let mut entries = parse(&embedded_source()?)?;
+        if let Some(path) = utils::config_path("layouts.toml") {
+            if path.exists() {
+                let source = fs::read_to_string(&path)?;
+                let overrides = parse(&source)
+                    .map_err(|err| TaskError::Layout(format!("{}: {}", path.display(), err)))?;
+                merge(&mut entries, overrides);
+            }
+        }
+        Self::resolve(entries)
+    }
+
+    /// Parses a layout file on its own, without the embedded layouts.
+    pub fn from_toml(source: &str) -> Result<Self, TaskError> {
+        Self::resolve(parse(source)?)
+    }
+
+    /// Layout of `resolution`, scaled from the reference one when it is not
+    /// configured.
+    pub fn get(&self, resolution: Resolution) -> Layout {
+        match self.layouts.get(&resolution) {
+            Some(layout) => layout.clone(),
+            None => self.layouts[&REFERENCE_RESOLUTION].scale(REFERENCE_RESOLUTION, resolution),
+        }
+    }
+
+    /// Resolutions with a configured layout.
+    pub fn resolutions(&self) -> Vec<Resolution> {
+        let mut resolutions: Vec<Resolution> = self.layouts.keys().copied().collect();
+        resolutions.sort();
+        resolutions
+    }
+
+    fn resolve(entries: Vec<LayoutEntry>) -> Result<Self, TaskError> {
+        let mut layouts: HashMap<Resolution, Layout> = HashMap::new();
+        let mut pending = entries;
+        // entries scaled from another one wait until their source is resolved
+        while !pending.is_empty() {
+            let pending_count = pending.len();
+            let mut waiting = Vec::new();
+            for entry in pending {
+                let resolution = (entry.width, entry.height);
+                let scaled = match entry.scale_from {
+                    Some(source) => match layouts.get(&source) {
+                        Some(layout) => Some(layout.scale(source, resolution)),
+                        None => {
+                            waiting.push(entry);
+                            continue;
+                        }
+                    },
+                    None => None,
+                };
+                let layout = match scaled {
+                    Some(scaled) => Layout {
+                        casino: entry.casino.unwrap_or(scaled.casino),
+                        cayo: entry.cayo.unwrap_or(scaled.cayo),
+                    },
+                    None => Layout {
+                        casino: entry.casino.ok_or_else(|| missing(resolution, "casino"))?,
+                        cayo: entry.cayo.ok_or_else(|| missing(resolution, "cayo"))?,
+                    },
+                };
+                layout.validate(resolution)?;
+                layouts.insert(resolution, layout);
+            }
+            if waiting.len() == pending_count {
+                let entry = &waiting[0];
+                let source = entry.scale_from.unwrap();
+                return Err(TaskError::Layout(format!(
+                    "{}x{} is scaled from {}x{} which has no layout",
+                    entry.width, entry.height, source.0, source.1
+                )));
+            }
+            pending = waiting;
+        }
+        if !layouts.contains_key(&REFERENCE_RESOLUTION) {
+            return Err(TaskError::Layout(format!(
+                "the {}x{} reference layout is missing",
+                REFERENCE_RESOLUTION.0, REFERENCE_RESOLUTION.1
+            )));
+        }
+        Ok(Layouts { layouts })
+    }
+}
+
+impl Layout {
+    fn scale(&self, from: Resolution, to: Resolution) -> Layout {
+        Layout {
+            casino: self.casino.scale(from, to),
+            cayo: self.cayo.scale(from, to),
+        }
+    }
+
+    fn validate(&self, resolution: Resolution) -> Result<(), TaskError> {
+        self.casino
+            .validate(resolution, "casino", CASINO_SLOT_COUNT)?;
+        self.cayo.validate(resolution, "cayo", ROW_COUNT)
+    }
+}
+
+impl TaskLayout {
+    fn scale(&self, from: Resolution, to: Resolution) -> TaskLayout {
+        TaskLayout {
+            header: scale_region(&self.header, from, to),
+            fingerprint: scale_region(&self.fingerprint, from, to),
+            parts: self
+                .parts
+                .iter()
+                .map(|region| scale_region(region, from, to))
+                .collect(),
+            result: self
+                .result
+                .as_ref()
+                .map(|region| scale_region(region, from, to)),
+        }
+    }
+
+    fn validate(
+        &self,
+        resolution: Resolution,
+        task: &str,
+        part_count: usize,
+    ) -> Result<(), TaskError> {
+        if self.parts.len() != part_count {
+            return Err(TaskError::Layout(format!(
+                "{}x{} {} has {} parts instead of {}",
+                resolution.0,
+                resolution.1,
+                task,
+                self.parts.len(),
+                part_count
+            )));
+        }
+        let regions = [&self.header, &self.fingerprint]
+            .into_iter()
+            .chain(self.parts.iter())
+            .chain(self.result.iter());
+        for region in regions {
+            if region[0] >= region[2]
+                || region[1] >= region[3]
+                || region[2] > resolution.0
+                || region[3] > resolution.1
+            {
+                return Err(TaskError::Layout(format!(
+                    "{}x{} {} region {:?} is empty or off screen",
+                    resolution.0, resolution.1, task, region
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Area covered by the game UI, which keeps a 16:9 ratio and is centered on
+/// wider (pillarbox) or taller (letterbox) screens. Returns (x, y, width, height).
+pub fn ui_area(resolution: Resolution) -> (f64, f64, f64, f64) {
+    let width = resolution.0 as f64;
+    let height = resolution.1 as f64;
+    let ui_width = width.min(height * 16.0 / 9.0);
+    let ui_height = height.min(width * 9.0 / 16.0);
+    (
+        (width - ui_width) / 2.0,
+        (height - ui_height) / 2.0,
+        ui_width,
+        ui_height,
+    )
+}
+
+pub fn scale_region(region: &Region, from: Resolution, to: Resolution) -> Region {
+    let (from_x, from_y, from_width, from_height) = ui_area(from);
+    let (to_x, to_y, to_width, to_height) = ui_area(to);
+    let scale_x = |x: u32| ((x as f64 - from_x) / from_width * to_width + to_x).round() as u32;
+    let scale_y = |y: u32| ((y as f64 - from_y) / from_height * to_height + to_y).round() as u32;
+    [
+        scale_x(region[0]),
+        scale_y(region[1]),
+        scale_x(region[2]),
+        scale_y(region[3]),
+    ]
+}
+
+fn embedded_source() -> Result<String, TaskError> {
+    let file = Asset::get(EMBEDDED_LAYOUTS)
+        .ok_or(TaskError::AssetMissing(EMBEDDED_LAYOUTS.to_string()))?;
+    String::from_utf8(file.data.into_owned())
+        .map_err(|err| TaskError::Layout(format!("{}: {}", EMBEDDED_LAYOUTS, err)))
+}
+
+fn parse(source: &str) -> Result<Vec<LayoutEntry>, TaskError> {
+    let file: LayoutFile =
+        toml::from_str(source).map_err(|err| TaskError::Layout(err.to_string()))?;
+    Ok(file.resolution)
+}
+
+/// Overrides replace the tasks (and scaling source) they define, other tasks
+/// keep their current layout.
+fn merge(entries: &mut Vec<LayoutEntry>, overrides: Vec<LayoutEntry>) {
+    for entry in overrides {
+        match entries
+            .iter_mut()
+            .find(|e| e.width == entry.width && e.height == entry.height)
+        {
+            Some(existing) => {
+                if entry.scale_from.is_some() {
+                    existing.scale_from = entry.scale_from;
+                }
+                if entry.casino.is_some() {
+                    existing.casino = entry.casino;
+                }
+                if entry.cayo.is_some() {
+                    existing.cayo = entry.cayo;
+                }
+            }
+            None => entries.push(entry),
+        }
+    }
+}
+
+fn missing(resolution: Resolution, task: &str) -> TaskError {
+    TaskError::Layout(format!(
+        "{}x{} has no {} layout and is not scaled from another resolution",
+        resolution.0, resolution.1, task
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::{R1080, R1440};
+
+    #[test]
+    fn embedded_layouts_scale_1440_from_1080() {
+        let layouts = Layouts::embedded().unwrap();
+        assert_eq!(layouts.resolutions(), vec![R1080, R1440]);
+        let layout = layouts.get(R1440);
+        assert_eq!(layout.casino.header, [493, 120, 2067, 160]);
+        assert_eq!(layout.cayo.parts[7], [551, 1185, 1093, 1265]);
+    }
+
+    #[test]
+    fn other_aspect_ratios_keep_the_ui_centered() {
+        let layouts = Layouts::embedded().unwrap();
+        // 21:9, the UI is 2560 wide and centered
+        let ultrawide = layouts.get((3440, 1440));
+        assert_eq!(ultrawide.casino.header, [933, 120, 2507, 160]);
+        // 16:10, the UI is 1080 high and centered
+        let tall = layouts.get((1920, 1200));
+        assert_eq!(tall.cayo.header, [600, 120, 1661, 187]);
+        let small = layouts.get((1600, 900));
+        assert_eq!(small.casino.fingerprint, [812, 131, 1100, 571]);
+    }
+
+    #[test]
+    fn overrides_replace_only_the_tasks_they_define() {
+        let mut entries = parse(&embedded_source().unwrap()).unwrap();
+        let overrides = parse(
+            r#"
+            [[resolution]]
+            width = 1920
+            height = 1080
+            [resolution.cayo]
+            header = [0, 0, 10, 10]
+            fingerprint = [0, 0, 10, 10]
+            parts = [[0, 0, 1, 1], [0, 0, 1, 1], [0, 0, 1, 1], [0, 0, 1, 1],
+                     [0, 0, 1, 1], [0, 0, 1, 1], [0, 0, 1, 1], [0, 0, 1, 1]]
+
+            [[resolution]]
+            width = 3840
+            height = 2160
+            scale_from = [1920, 1080]
+            "#,
+        )
+        .unwrap();
+        merge(&mut entries, overrides);
+        let layouts = Layouts::resolve(entries).unwrap();
+        let embedded = Layouts::embedded().unwrap();
+
+        let layout = layouts.get(R1080);
+        assert_eq!(layout.casino, embedded.get(R1080).casino);
+        assert_eq!(layout.cayo.header, [0, 0, 10, 10]);
+        assert_eq!(
+            layouts.get((3840, 2160)).casino.header,
+            [740, 180, 3100, 240]
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_scale_source_and_bad_regions() {
+        let unknown = "[[resolution]]\nwidth = 800\nheight = 600\nscale_from = [1, 1]\n";
+        assert!(matches!(
+            Layouts::from_toml(unknown),
+            Err(TaskError::Layout(_))
+        ));
+
+        let source = embedded_source()
+            .unwrap()
+            .replace("[370, 90, 1550, 120]", "[370, 90, 1550, 2000]");
+        assert!(matches!(
+            Layouts::from_toml(&source),
+            Err(TaskError::Layout(_))
+        ));
+    }
+
+    #[test]
+    fn result_regions_are_optional_and_scaled() {
+        let embedded = Layouts::embedded().unwrap();
+        assert_eq!(embedded.get(R1080).casino.result, None);
+
+        let source = embedded_source().unwrap().replace(
+            "header = [370, 90, 1550, 120]",
+            "header = [370, 90, 1550, 120]\nresult = [660, 480, 1260, 600]",
+        );
+        let layouts = Layouts::from_toml(&source).unwrap();
+        assert_eq!(
+            layouts.get(R1440).casino.result,
+            Some([880, 640, 1680, 800])
+        );
+        assert_eq!(layouts.get(R1440).cayo.result, None);
+    }
+}
@@ -1,3 +1,13 @@
+pub mod capture;
 pub mod constants;
+pub mod error;
+pub mod hotkey;
+pub mod input;
+pub mod layout;
+pub mod matcher;
+pub mod screen;
+pub mod solver;
+pub mod thresholds;
 pub mod utils;
+pub use error::TaskError;
 pub use utils::ThreadStatus;
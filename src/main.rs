@@ -1,14 +1,17 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 mod casino;
 mod cayo;
+mod cli;
 mod misc;
 
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
-use std::sync::{Arc, Mutex};
-use std::{fs, thread};
+use std::sync::Mutex;
+use std::{fs, process, thread};
 
+use clap::Parser;
+use cli::Cli;
 use directories::ProjectDirs;
 use fltk::dialog;
 use fltk::image::PngImage;
@@ -21,12 +24,15 @@ use fltk::{
     prelude::{GroupExt, WidgetExt, WindowExt},
     window::Window,
 };
+use gta_assistant::capture::{FrameSource, MonitorSource, ReplaySource};
+use gta_assistant::hotkey;
+use gta_assistant::input::{self, CancellableBackend};
 use gta_assistant::utils::{self, TaskData};
 use gta_assistant::{
-    ThreadStatus,
+    TaskError, ThreadStatus,
     utils::{Button, ButtonType},
 };
-use log::LevelFilter;
+use log::{LevelFilter, error};
 use log4rs::append::console::ConsoleAppender;
 use log4rs::append::file::FileAppender;
 use log4rs::config::{Appender, Config, Root};
@@ -38,10 +44,21 @@ const MIN_LOG_LEVEL: LevelFilter = LevelFilter::Debug;
 #[cfg(not(debug_assertions))]
 const MIN_LOG_LEVEL: LevelFilter = LevelFilter::Info;
 
+/// Messages sent to the ui thread.
+enum Message {
+    Alert(String),
+    Hotkey(usize),
+}
+
 struct AppState {
     running_threads: Mutex<HashMap<Button, ThreadStatus>>,
 }
 fn main() {
+    if std::env::args().len() > 1 {
+        cli::attach_console();
+    }
+    let cli = Cli::parse();
+
     // INIT LOGGER
     let log_folder = if cfg!(debug_assertions) {
         // Debug
@@ -91,60 +108,35 @@ fn main() {
         )
         .unwrap();
     log4rs::init_config(logger_config).unwrap();
+    if let Some(command) = cli.command {
+        process::exit(cli::run(command));
+    }
+
     // APP INIT
 
     let capture_enabled = std::env::var("ASSISTANT_CAPTURE").unwrap_or("0".to_string()) != "0"
         || cfg!(debug_assertions);
-    let mut buttons = vec![
-        vec![
-            Button {
-                disabled_text: "Enable Fingerprints (Casino)",
-                enabled_text: "Disable Fingerprints (Casino)",
-                task: casino::casino::handler,
-                btn_type: ButtonType::Toggle,
-            },
-            Button {
-                disabled_text: "Enable Fingerprints (Cayo)",
-                enabled_text: "Disable Fingerprints (Cayo)",
-                task: cayo::cayo::handler,
-                btn_type: ButtonType::Toggle,
-            },
-        ],
-        vec![Button {
-            disabled_text: "Enable No AFK",
-            enabled_text: "Disable No AFK",
-            task: misc::no_afk::handler,
-            btn_type: ButtonType::Toggle,
-        }],
-    ];
-    if capture_enabled {
-        buttons.push(vec![
-            Button {
-                disabled_text: "Enable Casino Capture",
-                enabled_text: "Disable Casino Capture",
-                task: casino::casino_capture::handler,
-                btn_type: ButtonType::Toggle,
-            },
-            Button {
-                disabled_text: "Enable Cayo Capture",
-                enabled_text: "Disable Cayo Capture",
-                task: cayo::cayo_capture::handler,
-                btn_type: ButtonType::Toggle,
-            },
-        ]);
-    }
+    // hotkeys can be bound to every task, even the hidden ones
+    let task_ids: Vec<&str> = buttons(true)
+        .iter()
+        .flatten()
+        .map(|button| button.id)
+        .collect();
+    let buttons = buttons(capture_enabled);
     let app_state = Rc::new(AppState {
         running_threads: Mutex::new(HashMap::new()),
     });
 
     let app = app::App::default();
 
-    let (s_err, r_err) = app::channel::<&str>();
-    app::add_idle3(move |_| {
-        if let Some(msg) = r_err.recv() {
-            dialog::alert(0, 0, msg);
-        }
+    let (s_msg, r_msg) = app::channel::<Message>();
+    let bindings = hotkey::load_bindings(&task_ids).unwrap_or_else(|err| {
+        error!("{}", err);
+        s_msg.send(Message::Alert(err.to_string()));
+        HashMap::new()
     });
+    let mut hotkeys = Vec::new();
+    let mut hotkey_buttons = Vec::new();
 
     let mut window = Window::default()
         .with_size(500, 200)
@@ -165,6 +157,11 @@ fn main() {
         for button_data in row {
             let mut toggle_button = create_button();
             toggle_button.set_label(button_data.disabled_text);
+            if let Some(hotkey) = bindings.get(button_data.id) {
+                toggle_button.set_tooltip(&format!("Hotkey: {}", hotkey));
+                hotkeys.push(*hotkey);
+                hotkey_buttons.push(toggle_button.clone());
+            }
 
             let button_data = (*button_data).clone();
             let app_state_ref = app_state.clone();
@@ -174,7 +171,7 @@ fn main() {
 
                 let mut running_threads = app_state_ref.running_threads.lock().unwrap();
                 let is_running = match running_threads.get(&button_data) {
-                    Some(signal) => *signal.lock().unwrap(),
+                    Some(thread_status) => thread_status.is_running(),
                     None => false,
                 };
                 if value {
@@ -182,38 +179,23 @@ fn main() {
                         // already running
                         return;
                     }
-                    let new_thread_status = Arc::new(Mutex::new(true));
+                    let new_thread_status = ThreadStatus::new();
                     running_threads.insert(button_data.clone(), new_thread_status.clone());
                     // spawn task from a thread that awaits for the end of the task to update ui
                     let button_data = button_data.clone();
                     let mut toggle_button = toggle_button.clone();
                     thread::spawn(move || {
-                        let task_data = TaskData {
-                            button: button_data.clone(),
-                            thread_status: new_thread_status.clone(),
-                        };
-                        let handle = (button_data.task)(task_data);
-
-                        let task_result = handle.join();
-                        match task_result {
-                            Ok(()) => {}
-                            Err(err) => {
-                                let error_str = err.downcast::<&str>().unwrap();
-                                s_err.send(&error_str);
-                            }
+                        if let Err(msg) = run_task(&button_data, new_thread_status.clone(), false) {
+                            s_msg.send(Message::Alert(msg));
                         }
                         toggle_button.set_value(false);
-                        if let Ok(mut guard) = new_thread_status.lock() {
-                            *guard = false;
-                        }
+                        new_thread_status.stop();
                         change_button_value(&mut toggle_button, &button_data, false);
                     });
                 } else {
                     if is_running {
                         if let Some(thread_status) = running_threads.get(&button_data) {
-                            if let Ok(mut signal) = thread_status.lock() {
-                                *signal = false;
-                            }
+                            thread_status.stop();
                         }
                     }
                 }
@@ -226,9 +208,120 @@ fn main() {
     col.end();
     window.show();
 
+    // hotkeys toggle their button like a click would, so the ui stays in sync
+    if let Err(err) = hotkey::listen(hotkeys, move |index| s_msg.send(Message::Hotkey(index))) {
+        error!("{}", err);
+        s_msg.send(Message::Alert(err.to_string()));
+    }
+    app::add_idle3(move |_| match r_msg.recv() {
+        Some(Message::Alert(msg)) => dialog::alert(0, 0, &msg),
+        Some(Message::Hotkey(index)) => {
+            let button = &mut hotkey_buttons[index];
+            button.set_value(!button.value());
+            button.do_callback();
+        }
+        None => {}
+    });
+
     app.run().unwrap();
 }
 
+/// Buttons of the ui, by row. Capture tasks are only listed when
+/// `capture_enabled`.
+fn buttons(capture_enabled: bool) -> Vec<Vec<Button>> {
+    let mut buttons = vec![
+        vec![
+            Button {
+                id: "casino",
+                disabled_text: "Enable Fingerprints (Casino)",
+                enabled_text: "Disable Fingerprints (Casino)",
+                task: casino::casino::handler,
+                btn_type: ButtonType::Toggle,
+            },
+            Button {
+                id: "cayo",
+                disabled_text: "Enable Fingerprints (Cayo)",
+                enabled_text: "Disable Fingerprints (Cayo)",
+                task: cayo::cayo::handler,
+                btn_type: ButtonType::Toggle,
+            },
+        ],
+        vec![Button {
+            id: "no-afk",
+            disabled_text: "Enable No AFK",
+            enabled_text: "Disable No AFK",
+            task: misc::no_afk::handler,
+            btn_type: ButtonType::Toggle,
+        }],
+    ];
+    if capture_enabled {
+        buttons.push(vec![
+            Button {
+                id: "casino-capture",
+                disabled_text: "Enable Casino Capture",
+                enabled_text: "Disable Casino Capture",
+                task: casino::casino_capture::handler,
+                btn_type: ButtonType::Toggle,
+            },
+            Button {
+                id: "cayo-capture",
+                disabled_text: "Enable Cayo Capture",
+                enabled_text: "Disable Cayo Capture",
+                task: cayo::cayo_capture::handler,
+                btn_type: ButtonType::Toggle,
+            },
+        ]);
+    }
+    buttons
+}
+
+/// Runs the task of a button until it stops, returning the message to show
+/// the user if it failed.
+fn run_task(button: &Button, thread_status: ThreadStatus, once: bool) -> Result<(), String> {
+    let backends = input::default_backend().and_then(|input| Ok((input, create_frame_source()?)));
+    let (input, frames) = match backends {
+        Ok(backends) => backends,
+        Err(err) => {
+            error!("{}", err);
+            return Err(err.to_string());
+        }
+    };
+    let task_data = TaskData {
+        button: button.clone(),
+        // keys stop as soon as the task is stopped, even mid-sequence
+        input: Box::new(CancellableBackend::new(input, thread_status.clone())),
+        thread_status,
+        once,
+        frames,
+    };
+    match (button.task)(task_data).join() {
+        Ok(Ok(())) | Ok(Err(TaskError::Cancelled)) => Ok(()),
+        Ok(Err(err)) => {
+            error!("{}", err);
+            Err(err.to_string())
+        }
+        Err(panic) => {
+            let msg = match panic.downcast::<String>() {
+                Ok(msg) => *msg,
+                Err(panic) => match panic.downcast::<&str>() {
+                    Ok(msg) => msg.to_string(),
+                    Err(_) => "The task stopped unexpectedly".to_string(),
+                },
+            };
+            error!("{}", msg);
+            Err(msg)
+        }
+    }
+}
+
+fn create_frame_source() -> Result<Box<dyn FrameSource>, TaskError> {
+    // replay a recorded session instead of capturing the screen
+    match std::env::var("ASSISTANT_REPLAY") {
+        Ok(path) => Ok(Box::new(ReplaySource::from_path(Path::new(&path))?)),
+        Err(_) => Ok(Box::new(MonitorSource::primary()?)),
+    }
+}
+
 fn create_button() -> ToggleButton {
     let mut button = ToggleButton::default();
     button.set_color(Color::from_hex(0x0d6efd));
@@ -0,0 +1,352 @@
+use crate::error::TaskError;
+use crate::utils::{self, MATCH_POOL};
+use image::{GrayImage, RgbImage, imageops, imageops::FilterType};
+use image_hasher::{HashAlg, Hasher, HasherConfig, ImageHash};
+use rayon::prelude::*;
+use serde::Deserialize;
+use std::fmt;
+
+/// What a matcher keeps of an image to compare it with others, so that
+/// reference images are only processed once.
+#[derive(Debug, Clone)]
+pub enum Features {
+    Hash(ImageHash),
+    /// Grayscale pixels, resized to a square.
+    Pixels(GrayImage),
+}
+
+/// Way of telling how similar two images are.
+pub trait Matcher: Send + Sync {
+    fn features(&self, image: &RgbImage) -> Features;
+
+    /// Similarity of two images from their features, from 0 (unrelated) to 1
+    /// (identical). Fails on features computed by another kind of matcher.
+    fn similarity(&self, a: &Features, b: &Features) -> Result<f64, TaskError>;
+}
+
+fn other_features() -> TaskError {
+    TaskError::Matcher("features of another matcher".to_string())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HashAlgorithm {
+    Mean,
+    Median,
+    Gradient,
+    VertGradient,
+    DoubleGradient,
+    Blockhash,
+}
+
+/// Matcher of a task, as written in `thresholds.toml`.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum MatcherConfig {
+    /// Perceptual hash of `size`x`size` bits, optionally computed on the
+    /// discrete cosine transform of the image.
+    Hash {
+        algorithm: HashAlgorithm,
+        size: u32,
+        #[serde(default)]
+        dct: bool,
+    },
+    /// Normalized cross-correlation of the grayscale images resized to
+    /// `size`x`size`.
+    Ncc { size: u32 },
+    /// Structural similarity of the grayscale images resized to `size`x`size`,
+    /// averaged over 8x8 blocks.
+    Ssim { size: u32 },
+    /// Mean squared error of the grayscale images resized to `size`x`size`.
+    Mse { size: u32 },
+}
+
+impl MatcherConfig {
+    pub fn build(&self) -> Result<Box<dyn Matcher>, TaskError> {
+        let size = match *self {
+            MatcherConfig::Hash { size, .. }
+            | MatcherConfig::Ncc { size }
+            | MatcherConfig::Ssim { size }
+            | MatcherConfig::Mse { size } => size,
+        };
+        if size == 0 {
+            return Err(TaskError::Thresholds(format!("{} has an empty size", self)));
+        }
+        Ok(match *self {
+            MatcherConfig::Hash {
+                algorithm,
+                size,
+                dct,
+            } => {
+                let mut config = HasherConfig::new()
+                    .hash_alg(algorithm.into())
+                    .hash_size(size, size);
+                if dct {
+                    config = config.preproc_dct();
+                }
+                Box::new(HashMatcher {
+                    hasher: config.to_hasher(),
+                })
+            }
+            MatcherConfig::Ncc { size } => Box::new(PixelMatcher { size, metric: ncc }),
+            MatcherConfig::Ssim { size } => Box::new(PixelMatcher { size, metric: ssim }),
+            MatcherConfig::Mse { size } => Box::new(PixelMatcher { size, metric: mse }),
+        })
+    }
+}
+
+impl fmt::Display for MatcherConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MatcherConfig::Hash {
+                algorithm,
+                size,
+                dct,
+            } => {
+                write!(f, "{} hash {}x{}", algorithm, size, size)?;
+                if *dct {
+                    write!(f, " (dct)")?;
+                }
+                Ok(())
+            }
+            MatcherConfig::Ncc { size } => write!(f, "ncc {}x{}", size, size),
+            MatcherConfig::Ssim { size } => write!(f, "ssim {}x{}", size, size),
+            MatcherConfig::Mse { size } => write!(f, "mse {}x{}", size, size),
+        }
+    }
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            HashAlgorithm::Mean => "mean",
+            HashAlgorithm::Median => "median",
+            HashAlgorithm::Gradient => "gradient",
+            HashAlgorithm::VertGradient => "vert-gradient",
+            HashAlgorithm::DoubleGradient => "double-gradient",
+            HashAlgorithm::Blockhash => "blockhash",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl From<HashAlgorithm> for HashAlg {
+    fn from(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Mean => HashAlg::Mean,
+            HashAlgorithm::Median => HashAlg::Median,
+            HashAlgorithm::Gradient => HashAlg::Gradient,
+            HashAlgorithm::VertGradient => HashAlg::VertGradient,
+            HashAlgorithm::DoubleGradient => HashAlg::DoubleGradient,
+            HashAlgorithm::Blockhash => HashAlg::Blockhash,
+        }
+    }
+}
+
+struct HashMatcher {
+    hasher: Hasher,
+}
+
+impl Matcher for HashMatcher {
+    fn features(&self, image: &RgbImage) -> Features {
+        Features::Hash(self.hasher.hash_image(image))
+    }
+
+    fn similarity(&self, a: &Features, b: &Features) -> Result<f64, TaskError> {
+        match (a, b) {
+            (Features::Hash(a), Features::Hash(b)) => Ok(utils::hash_similarity(a, b)),
+            _ => Err(other_features()),
+        }
+    }
+}
+
+struct PixelMatcher {
+    size: u32,
+    metric: fn(&GrayImage, &GrayImage) -> f64,
+}
+
+impl Matcher for PixelMatcher {
+    fn features(&self, image: &RgbImage) -> Features {
+        let gray = imageops::grayscale(image);
+        Features::Pixels(imageops::resize(
+            &gray,
+            self.size,
+            self.size,
+            FilterType::Triangle,
+        ))
+    }
+
+    fn similarity(&self, a: &Features, b: &Features) -> Result<f64, TaskError> {
+        match (a, b) {
+            (Features::Pixels(a), Features::Pixels(b)) => Ok((self.metric)(a, b)),
+            _ => Err(other_features()),
+        }
+    }
+}
+
+fn pixels(image: &GrayImage) -> impl Iterator<Item = f64> + '_ {
+    image.as_raw().iter().map(|pixel| *pixel as f64)
+}
+
+/// Correlation of the pixels, negative correlations count as unrelated.
+fn ncc(a: &GrayImage, b: &GrayImage) -> f64 {
+    let count = a.as_raw().len() as f64;
+    let mean_a = pixels(a).sum::<f64>() / count;
+    let mean_b = pixels(b).sum::<f64>() / count;
+    let (mut cross, mut var_a, mut var_b) = (0.0, 0.0, 0.0);
+    for (a, b) in pixels(a).zip(pixels(b)) {
+        cross += (a - mean_a) * (b - mean_b);
+        var_a += (a - mean_a).powi(2);
+        var_b += (b - mean_b).powi(2);
+    }
+    if var_a == 0.0 || var_b == 0.0 {
+        // flat images only correlate with flat images of the same shade
+        return if var_a == var_b {
+            1.0 - (mean_a - mean_b).abs() / 255.0
+        } else {
+            0.0
+        };
+    }
+    (cross / (var_a * var_b).sqrt()).max(0.0)
+}
+
+const SSIM_BLOCK: u32 = 8;
+
+fn ssim(a: &GrayImage, b: &GrayImage) -> f64 {
+    let c1 = (0.01 * 255.0_f64).powi(2);
+    let c2 = (0.03 * 255.0_f64).powi(2);
+    let block = SSIM_BLOCK.min(a.width());
+    let mut total = 0.0;
+    let mut count = 0;
+    for y in (0..=a.height() - block).step_by(block as usize) {
+        for x in (0..=a.width() - block).step_by(block as usize) {
+            let crop_a = imageops::crop_imm(a, x, y, block, block).to_image();
+            let crop_b = imageops::crop_imm(b, x, y, block, block).to_image();
+            let n = (block * block) as f64;
+            let mean_a = pixels(&crop_a).sum::<f64>() / n;
+            let mean_b = pixels(&crop_b).sum::<f64>() / n;
+            let (mut cov, mut var_a, mut var_b) = (0.0, 0.0, 0.0);
+            for (a, b) in pixels(&crop_a).zip(pixels(&crop_b)) {
+                cov += (a - mean_a) * (b - mean_b);
+                var_a += (a - mean_a).powi(2);
+                var_b += (b - mean_b).powi(2);
+            }
+            let (cov, var_a, var_b) = (cov / n, var_a / n, var_b / n);
+            total += ((2.0 * mean_a * mean_b + c1) * (2.0 * cov + c2))
+                / ((mean_a.powi(2) + mean_b.powi(2) + c1) * (var_a + var_b + c2));
+            count += 1;
+        }
+    }
+    (total / count as f64).clamp(0.0, 1.0)
+}
+
+fn mse(a: &GrayImage, b: &GrayImage) -> f64 {
+    let count = a.as_raw().len() as f64;
+    let error: f64 = pixels(a).zip(pixels(b)).map(|(a, b)| (a - b).powi(2)).sum();
+    1.0 - error / count / 255.0_f64.powi(2)
+}
+
+/// Features of `images`, computed in parallel.
+pub fn features_of(matcher: &dyn Matcher, images: &[RgbImage]) -> Vec<Features> {
+    MATCH_POOL.install(|| {
+        images
+            .par_iter()
+            .map(|image| matcher.features(image))
+            .collect()
+    })
+}
+
+/// Similarity between `target` and each of `others`.
+pub fn compare(
+    matcher: &dyn Matcher,
+    target: &Features,
+    others: &[Features],
+) -> Result<Vec<f64>, TaskError> {
+    others
+        .iter()
+        .map(|other| matcher.similarity(target, other))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+
+    fn gradient(size: u32, shift: u32) -> RgbImage {
+        RgbImage::from_fn(size, size, |x, y| {
+            let value = ((x * 7 + y * 3 + shift) % 256) as u8;
+            Rgb([value, value / 2, 255 - value])
+        })
+    }
+
+    #[test]
+    fn every_matcher_ranks_identical_images_first() {
+        let configs = [
+            MatcherConfig::Hash {
+                algorithm: HashAlgorithm::Gradient,
+                size: 8,
+                dct: false,
+            },
+            MatcherConfig::Hash {
+                algorithm: HashAlgorithm::Mean,
+                size: 16,
+                dct: true,
+            },
+            MatcherConfig::Ncc { size: 32 },
+            MatcherConfig::Ssim { size: 32 },
+            MatcherConfig::Mse { size: 32 },
+        ];
+        let target = gradient(64, 0);
+        let other = gradient(64, 128);
+        for config in configs {
+            let matcher = config.build().unwrap();
+            let features = features_of(matcher.as_ref(), &[target.clone(), other.clone()]);
+            let scores = compare(matcher.as_ref(), &features[0], &features).unwrap();
+            assert!((scores[0] - 1.0).abs() < 1e-9, "{}: {:?}", config, scores);
+            assert!(scores[1] < scores[0], "{}: {:?}", config, scores);
+            assert!((0.0..=1.0).contains(&scores[1]), "{}: {:?}", config, scores);
+        }
+    }
+
+    #[test]
+    fn parses_matcher_configs() {
+        #[derive(Deserialize)]
+        struct Config {
+            matcher: MatcherConfig,
+        }
+        let config: Config = toml::from_str(
+            "matcher = { kind = \"hash\", algorithm = \"double-gradient\", size = 16 }",
+        )
+        .unwrap();
+        assert_eq!(
+            config.matcher,
+            MatcherConfig::Hash {
+                algorithm: HashAlgorithm::DoubleGradient,
+                size: 16,
+                dct: false,
+            }
+        );
+        let config: Config = toml::from_str("matcher = { kind = \"ssim\", size = 32 }").unwrap();
+        assert_eq!(config.matcher, MatcherConfig::Ssim { size: 32 });
+        assert!(MatcherConfig::Ncc { size: 0 }.build().is_err());
+        assert!(toml::from_str::<Config>("matcher = { kind = \"sift\" }").is_err());
+    }
+
+    #[test]
+    fn features_of_another_matcher_are_refused() {
+        let image = gradient(32, 0);
+        let hash = MatcherConfig::Hash {
+            algorithm: HashAlgorithm::Gradient,
+            size: 8,
+            dct: false,
+        }
+        .build()
+        .unwrap();
+        let ncc = MatcherConfig::Ncc { size: 8 }.build().unwrap();
+        assert!(matches!(
+            ncc.similarity(&ncc.features(&image), &hash.features(&image)),
+            Err(TaskError::Matcher(_))
+        ));
+    }
+}
@@ -1,26 +1,34 @@
-use gta_assistant::utils::{self, TaskData, TaskResult};
+use gta_assistant::{
+    input::Key,
+    utils::{TaskData, TaskResult},
+};
 use log::info;
 use std::{thread, time::Duration};
-use windows::Win32::UI::Input::KeyboardAndMouse::{VK_S, VK_Z};
 
 pub fn handler(task_data: TaskData) -> TaskResult {
     thread::Builder::new()
         .name("no afk".to_string())
         .spawn(move || {
             info!("Thread started");
+            let mut input = task_data.input;
 
             let sleep_duration = Duration::from_secs(10);
             loop {
-                if !utils::check_thread_status(&task_data.thread_status) {
+                if !task_data.thread_status.is_running() {
                     break;
                 }
 
-                utils::press(VK_Z);
-                thread::sleep(sleep_duration);
-                utils::press(VK_S);
-                thread::sleep(sleep_duration);
+                input.press(Key::Letter('z'))?;
+                if !task_data.thread_status.sleep(sleep_duration) {
+                    break;
+                }
+                input.press(Key::Letter('s'))?;
+                if !task_data.thread_status.sleep(sleep_duration) {
+                    break;
+                }
             }
             info!("Stopping thread");
+            Ok(())
         })
         .unwrap()
 }
@@ -0,0 +1,564 @@
+use crate::capture::FrameSource;
+use crate::error::TaskError;
+use crate::layout::{self, Layout, Layouts, REFERENCE_RESOLUTION, TaskLayout};
+use crate::matcher::{Features, HashAlgorithm, Matcher, MatcherConfig};
+use crate::solver::{self, reference_assets};
+use crate::thresholds::{TaskThresholds, Thresholds};
+use crate::utils::{self, Asset, Region, Resolution};
+use image::RgbImage;
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+
+const EMBEDDED_SCREENS: &str = "assets/screens.toml";
+
+/// Game screens the assistant can tell apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScreenState {
+    CasinoFingerprint,
+    CasinoKeypad,
+    CayoFingerprint,
+    Success,
+    Failure,
+    PauseMenu,
+    Loading,
+    Unknown,
+}
+
+/// Change of screen seen by a `ScreenWatcher`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Transition {
+    pub from: ScreenState,
+    pub to: ScreenState,
+}
+
+/// What a region of the screen shows when the game is in `state`.
+struct Signature {
+    state: ScreenState,
+    region: Region,
+    /// Factor turning crops of `region` into the size of the reference image.
+    scale: f64,
+    matcher: Box<dyn Matcher>,
+    features: Features,
+    threshold: f64,
+}
+
+/// Tells which registered screen a frame shows, from one region per screen.
+#[derive(Default)]
+pub struct ScreenClassifier {
+    signatures: Vec<Signature>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ScreensFile {
+    #[serde(default)]
+    screen: Vec<ScreenEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ScreenEntry {
+    state: ScreenState,
+    /// Embedded image when under `assets/`, relative to the config folder
+    /// otherwise.
+    image: PathBuf,
+    /// Region of the reference resolution, scaled like layouts.
+    region: Region,
+    threshold: f64,
+    #[serde(default = "default_matcher")]
+    matcher: MatcherConfig,
+}
+
+fn default_matcher() -> MatcherConfig {
+    MatcherConfig::Hash {
+        algorithm: HashAlgorithm::Gradient,
+        size: 8,
+        dct: false,
+    }
+}
+
+impl ScreenClassifier {
+    /// Classifier of the fingerprint and result screens of both tasks, plus
+    /// the screens of the embedded and user `screens.toml`, using the
+    /// configured layouts and thresholds.
+    pub fn new(resolution: Resolution) -> Result<Self, TaskError> {
+        let layout = Layouts::load()?.get(resolution);
+        let mut classifier = Self::with_config(resolution, &layout, &Thresholds::load()?)?;
+        if let Some(path) = utils::config_path("screens.toml") {
+            if path.exists() {
+                let source = fs::read_to_string(&path)?;
+                let entries = parse(&source)
+                    .map_err(|err| TaskError::Screens(format!("{}: {}", path.display(), err)))?;
+                classifier.register_entries(resolution, entries)?;
+            }
+        }
+        Ok(classifier)
+    }
+
+    /// Classifier of the fingerprint and result screens of both tasks and of
+    /// the embedded `screens.toml`.
+    pub fn with_config(
+        resolution: Resolution,
+        layout: &Layout,
+        thresholds: &Thresholds,
+    ) -> Result<Self, TaskError> {
+        let mut classifier = ScreenClassifier::default();
+        classifier.register_task(
+            resolution,
+            "casino",
+            ScreenState::CasinoFingerprint,
+            &layout.casino,
+            &thresholds.casino,
+        )?;
+        classifier.register_task(
+            resolution,
+            "cayo",
+            ScreenState::CayoFingerprint,
+            &layout.cayo,
+            &thresholds.cayo,
+        )?;
+        classifier.register_entries(resolution, parse(&embedded_source()?)?)?;
+        Ok(classifier)
+    }
+
+    fn register_entries(
+        &mut self,
+        resolution: Resolution,
+        entries: Vec<ScreenEntry>,
+    ) -> Result<(), TaskError> {
+        let scale = REFERENCE_RESOLUTION.1 as f64 / layout::ui_area(resolution).3;
+        for entry in entries {
+            let region = layout::scale_region(&entry.region, REFERENCE_RESOLUTION, resolution);
+            if region[0] >= region[2]
+                || region[1] >= region[3]
+                || region[2] > resolution.0
+                || region[3] > resolution.1
+            {
+                return Err(TaskError::Screens(format!(
+                    "{:?} region {:?} is empty or off screen",
+                    entry.state, region
+                )));
+            }
+            let image = utils::load_image(image_path(&entry.image))?;
+            self.register(
+                entry.state,
+                region,
+                scale,
+                entry.matcher.build()?,
+                &image,
+                entry.threshold,
+            );
+        }
+        Ok(())
+    }
+
+    /// Registers the header of a fingerprint task, and its result screens
+    /// when it has a result region and reference images.
+    fn register_task(
+        &mut self,
+        resolution: Resolution,
+        task: &str,
+        state: ScreenState,
+        layout: &TaskLayout,
+        thresholds: &TaskThresholds,
+    ) -> Result<(), TaskError> {
+        let (folder, scale) = reference_assets(resolution, task);
+        let image = utils::load_image(folder.join("header.png"))?;
+        self.register(
+            state,
+            layout.header,
+            scale,
+            thresholds.matcher.build()?,
+            &image,
+            solver::header_threshold(thresholds, scale),
+        );
+        if let Some(region) = layout.result {
+            for (state, file) in [
+                (ScreenState::Success, "success.png"),
+                (ScreenState::Failure, "failure.png"),
+            ] {
+                match utils::load_image(folder.join(file)) {
+                    Ok(image) => self.register(
+                        state,
+                        region,
+                        scale,
+                        thresholds.matcher.build()?,
+                        &image,
+                        thresholds.result,
+                    ),
+                    Err(TaskError::AssetMissing(_)) => {}
+                    Err(err) => return Err(err),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Recognizes `state` when crops of `region`, rescaled by `scale`, are
+    /// more similar than `threshold` to `reference`.
+    pub fn register(
+        &mut self,
+        state: ScreenState,
+        region: Region,
+        scale: f64,
+        matcher: Box<dyn Matcher>,
+        reference: &RgbImage,
+        threshold: f64,
+    ) {
+        let features = matcher.features(reference);
+        self.signatures.push(Signature {
+            state,
+            region,
+            scale,
+            matcher,
+            features,
+            threshold,
+        });
+    }
+
+    /// Regions to capture, in the order expected by `classify`.
+    pub fn regions(&self) -> Vec<Region> {
+        self.signatures
+            .iter()
+            .map(|signature| signature.region)
+            .collect()
+    }
+
+    /// Screen whose signature is the most similar to its crop, among the ones
+    /// above their threshold.
+    pub fn classify(&self, crops: &[RgbImage]) -> Result<ScreenState, TaskError> {
+        let mut best: Option<(ScreenState, f64)> = None;
+        for (signature, crop) in self.signatures.iter().zip(crops) {
+            let crop = signature
+                .matcher
+                .features(&utils::rescale(crop, signature.scale));
+            let similarity = signature.matcher.similarity(&signature.features, &crop)?;
+            if similarity > signature.threshold && best.is_none_or(|(_, best)| similarity > best) {
+                best = Some((signature.state, similarity));
+            }
+        }
+        Ok(best.map_or(ScreenState::Unknown, |(state, _)| state))
+    }
+}
+
+/// Follows the screen from frame to frame, so that tasks react to the
+/// transitions they care about instead of checking the screen themselves.
+pub struct ScreenWatcher {
+    classifier: ScreenClassifier,
+    regions: Vec<Region>,
+    crops: Vec<RgbImage>,
+    state: ScreenState,
+    /// Channels sent the transitions into their state.
+    subscribers: Vec<(ScreenState, Sender<Transition>)>,
+}
+
+impl ScreenWatcher {
+    pub fn new(classifier: ScreenClassifier) -> Self {
+        ScreenWatcher {
+            regions: classifier.regions(),
+            classifier,
+            crops: Vec::new(),
+            state: ScreenState::Unknown,
+            subscribers: Vec::new(),
+        }
+    }
+
+    pub fn state(&self) -> ScreenState {
+        self.state
+    }
+
+    /// Channel receiving every transition into `state` seen by `update`.
+    pub fn subscribe(&mut self, state: ScreenState) -> Receiver<Transition> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.push((state, sender));
+        receiver
+    }
+
+    /// Forgets the current screen, so that the next `update` reports it again
+    /// if it is still shown. Tasks call it once they handled a screen that
+    /// can show up again without leaving it, like the next fingerprint.
+    pub fn reset(&mut self) {
+        self.state = ScreenState::Unknown;
+    }
+
+    /// Classifies the current frame of `frames`, returning the transition
+    /// when the screen changed.
+    pub fn update(
+        &mut self,
+        frames: &mut dyn FrameSource,
+    ) -> Result<Option<Transition>, TaskError> {
+        frames.capture_regions_into(&self.regions, &mut self.crops)?;
+        let state = self.classifier.classify(&self.crops)?;
+        let transition = (state != self.state).then_some(Transition {
+            from: self.state,
+            to: state,
+        });
+        self.state = state;
+        if let Some(transition) = transition {
+            // dropped receivers are unsubscribed
+            self.subscribers.retain(|(state, sender)| {
+                *state != transition.to || sender.send(transition).is_ok()
+            });
+        }
+        Ok(transition)
+    }
+}
+
+fn image_path(image: &Path) -> PathBuf {
+    if image.starts_with("assets") {
+        return image.to_path_buf();
+    }
+    match utils::config_path("") {
+        Some(config) => config.join(image),
+        None => image.to_path_buf(),
+    }
+}
+
+fn embedded_source() -> Result<String, TaskError> {
+    let file = Asset::get(EMBEDDED_SCREENS)
+        .ok_or(TaskError::AssetMissing(EMBEDDED_SCREENS.to_string()))?;
+    Ok(String::from_utf8_lossy(&file.data).into_owned())
+}
+
+fn parse(source: &str) -> Result<Vec<ScreenEntry>, TaskError> {
+    let file: ScreensFile =
+        toml::from_str(source).map_err(|err| TaskError::Screens(err.to_string()))?;
+    Ok(file.screen)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::ReplaySource;
+    use crate::constants::R1080;
+    use image::{DynamicImage, imageops};
+
+    fn classifier(resolution: Resolution) -> ScreenClassifier {
+        let layout = Layouts::embedded().unwrap().get(resolution);
+        ScreenClassifier::with_config(resolution, &layout, &Thresholds::embedded().unwrap())
+            .unwrap()
+    }
+
+    /// Blank frame with the header of `task` where its layout puts it.
+    fn header_frame(resolution: Resolution, task: &str) -> RgbImage {
+        let layout = Layouts::embedded().unwrap().get(resolution);
+        let region = match task {
+            "casino" => layout.casino.header,
+            _ => layout.cayo.header,
+        };
+        let (folder, scale) = reference_assets(resolution, task);
+        let header = utils::load_image(folder.join("header.png")).unwrap();
+        let mut frame = RgbImage::new(resolution.0, resolution.1);
+        imageops::replace(
+            &mut frame,
+            utils::rescale(&header, 1.0 / scale).as_ref(),
+            region[0] as i64,
+            region[1] as i64,
+        );
+        frame
+    }
+
+    fn crops(classifier: &ScreenClassifier, frame: &RgbImage) -> Vec<RgbImage> {
+        classifier
+            .regions()
+            .iter()
+            .map(|region| utils::crop_region(frame, region))
+            .collect()
+    }
+
+    #[test]
+    fn classifies_the_fingerprint_screens() {
+        for resolution in [R1080, (1600, 900)] {
+            let classifier = classifier(resolution);
+            for (task, state) in [
+                ("casino", ScreenState::CasinoFingerprint),
+                ("cayo", ScreenState::CayoFingerprint),
+            ] {
+                let frame = header_frame(resolution, task);
+                assert_eq!(
+                    classifier.classify(&crops(&classifier, &frame)).unwrap(),
+                    state,
+                    "{:?}",
+                    resolution
+                );
+            }
+            let blank = RgbImage::new(resolution.0, resolution.1);
+            assert_eq!(
+                classifier.classify(&crops(&classifier, &blank)).unwrap(),
+                ScreenState::Unknown
+            );
+        }
+    }
+
+    /// Distinct blocky pattern for each `seed`, standing in for a screen
+    /// signature.
+    fn pattern(seed: u32) -> RgbImage {
+        RgbImage::from_fn(160, 96, |x, y| {
+            let block = (x / 16 + 10 * (y / 16) + 1).wrapping_mul(seed.wrapping_mul(2654435761));
+            let value = (block >> 24) as u8;
+            image::Rgb([value, value.wrapping_mul(3), value ^ 0x5a])
+        })
+    }
+
+    #[test]
+    fn registers_screens_from_their_file() {
+        let mut classifier = classifier(R1080);
+        let folder =
+            std::env::temp_dir().join(format!("gta-assistant-screens-{}", std::process::id()));
+        fs::create_dir_all(&folder).unwrap();
+        let screens = [
+            (
+                ScreenState::CasinoKeypad,
+                "casino-keypad",
+                [100, 900, 260, 996],
+            ),
+            (ScreenState::PauseMenu, "pause-menu", [400, 900, 560, 996]),
+            (ScreenState::Loading, "loading", [700, 900, 860, 996]),
+        ];
+        let mut source = String::new();
+        for (seed, (_, name, region)) in screens.iter().enumerate() {
+            let path = folder.join(format!("{}.png", name));
+            pattern(seed as u32 + 1).save(&path).unwrap();
+            source += &format!(
+                "[[screen]]\nstate = \"{}\"\nimage = {:?}\nregion = {:?}\nthreshold = 0.9\n",
+                name, path, region
+            );
+        }
+        classifier
+            .register_entries(R1080, parse(&source).unwrap())
+            .unwrap();
+        fs::remove_dir_all(&folder).unwrap();
+
+        for (seed, (state, _, region)) in screens.iter().enumerate() {
+            let mut frame = RgbImage::new(R1080.0, R1080.1);
+            imageops::replace(
+                &mut frame,
+                &pattern(seed as u32 + 1),
+                region[0] as i64,
+                region[1] as i64,
+            );
+            assert_eq!(
+                classifier.classify(&crops(&classifier, &frame)).unwrap(),
+                *state
+            );
+        }
+        let blank = RgbImage::new(R1080.0, R1080.1);
+        assert_eq!(
+            classifier.classify(&crops(&classifier, &blank)).unwrap(),
+            ScreenState::Unknown
+        );
+
+        let off_screen = parse(
+            "[[screen]]\nstate = \"loading\"\nimage = \"assets/icon.png\"\nregion = [0, 0, 10, 2000]\nthreshold = 0.9",
+        )
+        .unwrap();
+        assert!(classifier.register_entries(R1080, off_screen).is_err());
+        assert!(parse("[[screen]]\nstate = \"lobby\"").is_err());
+    }
+
+    /// The keypad, pause menu and loading screens have no embedded reference,
+    /// see Hidden Features in the README to check the ones of a `screens.toml`
+    /// against screenshots taken in game.
+    #[test]
+    #[ignore = "needs screenshots captured in game"]
+    fn captured_screens_are_recognized() {
+        let classifier = ScreenClassifier::new(R1080).unwrap();
+        let folder = PathBuf::from("assets/1080/screens");
+        let mut checked = 0;
+        for (state, name) in [
+            (ScreenState::CasinoKeypad, "casino-keypad"),
+            (ScreenState::PauseMenu, "pause-menu"),
+            (ScreenState::Loading, "loading"),
+        ] {
+            for index in 1.. {
+                let Ok(frame) = utils::load_image(folder.join(format!("{}_{}.png", name, index)))
+                else {
+                    break;
+                };
+                assert_eq!(
+                    classifier.classify(&crops(&classifier, &frame)).unwrap(),
+                    state,
+                    "{}_{}",
+                    name,
+                    index
+                );
+                checked += 1;
+            }
+        }
+        assert!(checked > 0, "no captured screens in {}", folder.display());
+    }
+
+    #[test]
+    fn subscribers_receive_transitions_into_their_state() {
+        let frames = [
+            header_frame(R1080, "casino"),
+            RgbImage::new(R1080.0, R1080.1),
+            header_frame(R1080, "cayo"),
+            header_frame(R1080, "casino"),
+            header_frame(R1080, "casino"),
+        ];
+        let mut source = ReplaySource::from_frames(
+            frames
+                .into_iter()
+                .map(|frame| DynamicImage::ImageRgb8(frame).into_rgba8())
+                .collect(),
+        )
+        .unwrap();
+        let mut watcher = ScreenWatcher::new(classifier(R1080));
+        let casino = watcher.subscribe(ScreenState::CasinoFingerprint);
+        let cayo = watcher.subscribe(ScreenState::CayoFingerprint);
+        drop(watcher.subscribe(ScreenState::CasinoFingerprint));
+        let mut received = Vec::new();
+        while !source.is_exhausted() {
+            source.next_frame().unwrap();
+            watcher.update(&mut source).unwrap();
+            received.push((casino.try_iter().count(), cayo.try_iter().count()));
+            if source.frames_left() == 1 {
+                // the casino screen is handled, and still shown on the last frame
+                watcher.reset();
+            }
+        }
+        assert_eq!(received, vec![(1, 0), (0, 0), (0, 1), (1, 0), (1, 0)]);
+        assert_eq!(watcher.subscribers.len(), 2);
+    }
+
+    #[test]
+    fn watcher_reports_transitions() {
+        let frames = [
+            header_frame(R1080, "casino"),
+            header_frame(R1080, "casino"),
+            RgbImage::new(R1080.0, R1080.1),
+        ];
+        let mut source = ReplaySource::from_frames(
+            frames
+                .into_iter()
+                .map(|frame| DynamicImage::ImageRgb8(frame).into_rgba8())
+                .collect(),
+        )
+        .unwrap();
+        let mut watcher = ScreenWatcher::new(classifier(R1080));
+        let mut transitions = Vec::new();
+        while !source.is_exhausted() {
+            source.next_frame().unwrap();
+            transitions.push(watcher.update(&mut source).unwrap());
+        }
+        assert_eq!(
+            transitions,
+            vec![
+                Some(Transition {
+                    from: ScreenState::Unknown,
+                    to: ScreenState::CasinoFingerprint,
+                }),
+                None,
+                Some(Transition {
+                    from: ScreenState::CasinoFingerprint,
+                    to: ScreenState::Unknown,
+                }),
+            ]
+        );
+        assert_eq!(watcher.state(), ScreenState::Unknown);
+    }
+}
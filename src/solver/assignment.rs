@@ -0,0 +1,201 @@
+/// Rows matched to distinct columns of a similarity matrix.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assignment {
+    /// Column picked for each row.
+    pub columns: Vec<usize>,
+    /// Sum of the similarities of the picked cells.
+    pub total: f64,
+    /// Total of the best assignment differing from this one, `None` when
+    /// there is no other.
+    pub runner_up: Option<f64>,
+    /// Total of the best assignment using another set of columns, whatever
+    /// their order. `None` when every assignment uses the same columns.
+    pub set_runner_up: Option<f64>,
+    /// For each row, how much `total` beats the best assignment giving that
+    /// row another column.
+    pub row_margins: Vec<f64>,
+}
+
+impl Assignment {
+    /// How much better this assignment is than the runner-up.
+    pub fn margin(&self) -> f64 {
+        self.runner_up
+            .map_or(f64::INFINITY, |runner_up| self.total - runner_up)
+    }
+
+    /// How much better this assignment is than the best one using other
+    /// columns, for when the order of the columns does not matter.
+    pub fn set_margin(&self) -> f64 {
+        self.set_runner_up
+            .map_or(f64::INFINITY, |runner_up| self.total - runner_up)
+    }
+}
+
+/// Matches every row of `scores` to a distinct column, maximizing the total
+/// similarity. Tries every assignment, which is cheap for the 8 columns of the
+/// fingerprint puzzles (1680 for the casino, 40320 for cayo).
+pub fn best_assignment(scores: &[Vec<f64>]) -> Assignment {
+    let column_count = scores.first().map_or(0, Vec::len);
+    assert!(
+        scores.len() <= column_count,
+        "can not assign {} rows to {} columns",
+        scores.len(),
+        column_count
+    );
+    let mut search = Search {
+        scores,
+        used: vec![false; column_count],
+        current: Vec::with_capacity(scores.len()),
+        best: None,
+        runner_up: None,
+        best_by_cell: vec![vec![f64::NEG_INFINITY; column_count]; scores.len()],
+        best_by_set: vec![f64::NEG_INFINITY; 1 << column_count],
+    };
+    search.visit(0.0);
+
+    let (total, columns) = search.best.unwrap_or((0.0, Vec::new()));
+    let row_margins = columns
+        .iter()
+        .zip(&search.best_by_cell)
+        .map(|(column, totals)| {
+            let other = totals
+                .iter()
+                .enumerate()
+                .filter(|(other_column, _)| other_column != column)
+                .map(|(_, total)| *total)
+                .fold(f64::NEG_INFINITY, f64::max);
+            total - other
+        })
+        .collect();
+    let best_set = column_set(&columns);
+    let set_runner_up = search
+        .best_by_set
+        .iter()
+        .enumerate()
+        .filter(|(set, total)| *set != best_set && total.is_finite())
+        .map(|(_, total)| *total)
+        .reduce(f64::max);
+    Assignment {
+        columns,
+        total,
+        runner_up: search.runner_up,
+        set_runner_up,
+        row_margins,
+    }
+}
+
+/// Bit mask of `columns`.
+fn column_set(columns: &[usize]) -> usize {
+    columns.iter().fold(0, |set, column| set | 1 << column)
+}
+
+struct Search<'a> {
+    scores: &'a [Vec<f64>],
+    used: Vec<bool>,
+    current: Vec<usize>,
+    best: Option<(f64, Vec<usize>)>,
+    runner_up: Option<f64>,
+    /// Best total of the assignments giving column `[row][column]` to `row`.
+    best_by_cell: Vec<Vec<f64>>,
+    /// Best total of the assignments using each set of columns, by bit mask.
+    best_by_set: Vec<f64>,
+}
+
+impl Search<'_> {
+    fn visit(&mut self, total: f64) {
+        let row = self.current.len();
+        if row == self.scores.len() {
+            self.record(total);
+            return;
+        }
+        for column in 0..self.used.len() {
+            if self.used[column] {
+                continue;
+            }
+            self.used[column] = true;
+            self.current.push(column);
+            self.visit(total + self.scores[row][column]);
+            self.current.pop();
+            self.used[column] = false;
+        }
+    }
+
+    fn record(&mut self, total: f64) {
+        for (row, column) in self.current.iter().enumerate() {
+            let best = &mut self.best_by_cell[row][*column];
+            *best = best.max(total);
+        }
+        let best = &mut self.best_by_set[column_set(&self.current)];
+        *best = best.max(total);
+        match &self.best {
+            Some((best_total, _)) if total <= *best_total => {
+                if self.runner_up.is_none_or(|runner_up| total > runner_up) {
+                    self.runner_up = Some(total);
+                }
+            }
+            _ => {
+                if let Some((best_total, _)) = self.best.take() {
+                    self.runner_up = Some(best_total);
+                }
+                self.best = Some((total, self.current.clone()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows_never_share_a_column() {
+        // both rows prefer column 0, the second one less strongly
+        let scores = vec![vec![0.9, 0.5, 0.1], vec![0.8, 0.7, 0.2]];
+        let assignment = best_assignment(&scores);
+        assert_eq!(assignment.columns, vec![0, 1]);
+        assert!((assignment.total - 1.6).abs() < 1e-9);
+        // runner-up is row 0 on column 1 and row 1 on column 0
+        assert!((assignment.margin() - 0.3).abs() < 1e-9);
+    }
+
+    #[test]
+    fn set_runner_up_ignores_reordered_columns() {
+        // swapping the rows keeps the same columns, the runner-up set
+        // replaces column 1 with column 2
+        let scores = vec![vec![0.9, 0.8, 0.1], vec![0.85, 0.9, 0.5]];
+        let assignment = best_assignment(&scores);
+        assert_eq!(assignment.columns, vec![0, 1]);
+        assert!((assignment.margin() - 0.15).abs() < 1e-9);
+        assert!((assignment.set_margin() - 0.4).abs() < 1e-9);
+        assert_eq!(
+            best_assignment(&[vec![0.4, 0.6], vec![0.5, 0.1]]).set_runner_up,
+            None
+        );
+    }
+
+    #[test]
+    fn row_margins_point_at_the_uncertain_rows() {
+        // rows 1 and 2 look alike, row 0 is clear
+        let scores = vec![
+            vec![1.0, 0.1, 0.1],
+            vec![0.1, 0.8, 0.75],
+            vec![0.1, 0.75, 0.8],
+        ];
+        let assignment = best_assignment(&scores);
+        assert_eq!(assignment.columns, vec![0, 1, 2]);
+        assert!((assignment.row_margins[0] - 1.6).abs() < 1e-9);
+        assert!((assignment.row_margins[1] - 0.1).abs() < 1e-9);
+        assert!((assignment.row_margins[2] - 0.1).abs() < 1e-9);
+        assert!((assignment.margin() - 0.1).abs() < 1e-9);
+    }
+
+    #[test]
+    fn single_assignment_has_no_runner_up() {
+        let assignment = best_assignment(&[vec![0.4]]);
+        assert_eq!(assignment.columns, vec![0]);
+        assert_eq!(assignment.runner_up, None);
+        assert_eq!(assignment.set_runner_up, None);
+        assert_eq!(assignment.margin(), f64::INFINITY);
+        assert_eq!(assignment.row_margins, vec![f64::INFINITY]);
+    }
+}
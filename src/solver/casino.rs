@@ -0,0 +1,497 @@
+use super::assignment::best_assignment;
+use super::outcome::{self, Attempts};
+use super::{FingerprintSolver, Margins, SolveError, TaskAssets, check_thresholds};
+use crate::capture::{self, FrameSource};
+use crate::constants;
+use crate::error::TaskError;
+use crate::input::{InputBackend, Key};
+use crate::layout::{Layouts, TaskLayout};
+use crate::matcher;
+use crate::screen::{ScreenState, ScreenWatcher};
+use crate::thresholds::{TaskThresholds, Thresholds};
+use crate::utils::{self, Resolution, ThreadStatus};
+use image::{RgbImage, imageops};
+use log::{debug, info, warn};
+use std::ops::Deref;
+
+/// Reference images of the casino fingerprints.
+pub fn assets() -> TaskAssets {
+    TaskAssets {
+        name: "casino",
+        fingerprint_count: *constants::CASINO_FINGERPRINT_COUNT as usize,
+        fingerprint_file: "full.png",
+        part_count: 4,
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CasinoSolution {
+    pub fingerprint_index: usize,
+    pub fingerprint_score: f64,
+    /// Similarity of the second best matching fingerprint.
+    pub fingerprint_second: f64,
+    /// Similarity between the screen fingerprint and each known one.
+    pub fingerprint_scores: Vec<f64>,
+    /// Distinct slots (0 to 7, left to right then top to bottom) picked for
+    /// each of the 4 solution parts.
+    pub slots: Vec<usize>,
+    /// Similarity between each solution part and its picked slot.
+    pub part_scores: Vec<f64>,
+    /// Sum of `part_scores`.
+    pub total_score: f64,
+    /// How much `total_score` beats the best choice of other slots, the
+    /// order in which the parts take them not changing the keys.
+    pub margin: f64,
+    /// For each solution part, how much `total_score` beats the best choice
+    /// giving it another slot, including the slot of another part, which
+    /// keeps the same keys and is not checked for that reason.
+    pub part_margins: Vec<f64>,
+    /// Similarity between each solution part and each of the 8 slots.
+    pub scores: Vec<Vec<f64>>,
+    pub keys: Vec<Key>,
+}
+
+pub struct CasinoSolver {
+    core: FingerprintSolver,
+    /// `selected_change` of the thresholds, which only the casino has.
+    selected_change: f64,
+}
+
+impl Deref for CasinoSolver {
+    type Target = FingerprintSolver;
+
+    fn deref(&self) -> &FingerprintSolver {
+        &self.core
+    }
+}
+
+impl CasinoSolver {
+    /// Solver using the layout of `resolution` and the thresholds from the
+    /// embedded and user configuration files.
+    pub fn new(resolution: Resolution) -> Result<Self, TaskError> {
+        let layout = Layouts::load()?.get(resolution).casino;
+        Self::with_layout(resolution, layout, Thresholds::load()?.casino)
+    }
+
+    pub fn with_layout(
+        resolution: Resolution,
+        layout: TaskLayout,
+        thresholds: TaskThresholds,
+    ) -> Result<Self, TaskError> {
+        let selected_change = thresholds.selected_change.ok_or(TaskError::Thresholds(
+            "casino.selected_change is missing".to_string(),
+        ))?;
+        Ok(CasinoSolver {
+            core: FingerprintSolver::new(resolution, layout, thresholds, &assets())?,
+            selected_change,
+        })
+    }
+
+    /// Fails when `solution` is not confident enough to send its keys. Parts
+    /// that look alike may trade their slots, only another set of slots is
+    /// ambiguous.
+    pub fn check(&self, solution: &CasinoSolution) -> Result<(), SolveError> {
+        check_thresholds(
+            &self.thresholds,
+            solution.fingerprint_score,
+            solution.fingerprint_second,
+            &solution.part_scores,
+            Margins::Whole(solution.margin),
+        )
+    }
+
+    /// Solves every casino fingerprint `screen` sees on `frames` until the
+    /// task is stopped, the frames run out, or the first one was submitted
+    /// when `once` is set. Returns how the submitted fingerprints went.
+    pub fn run(
+        &self,
+        screen: &mut ScreenWatcher,
+        input: &mut dyn InputBackend,
+        frames: &mut dyn FrameSource,
+        thread_status: &ThreadStatus,
+        once: bool,
+    ) -> Result<Attempts, TaskError> {
+        let solve_regions = self.solve_regions_pos();
+        let result_regions = self.result_regions_pos();
+        let mut attempts = Attempts::default();
+        let fingerprints = screen.subscribe(ScreenState::CasinoFingerprint);
+        // crops are reused from frame to frame
+        let mut screenshots = Vec::new();
+
+        loop {
+            if !thread_status.is_running() || frames.is_exhausted() {
+                break;
+            };
+            frames.next_frame()?;
+
+            if let Some(transition) = screen.update(frames)? {
+                debug!(
+                    "Screen changed from {:?} to {:?}",
+                    transition.from, transition.to
+                );
+            }
+            if fingerprints.try_iter().last().is_some() {
+                // reported again on the next frame if it is still shown,
+                // to capture it again or solve the next one
+                screen.reset();
+                info!("Fingerprint detected");
+                // the fingerprint may still be animating in
+                if !capture::wait_until_stable(
+                    frames,
+                    thread_status,
+                    &solve_regions,
+                    &mut screenshots,
+                    *constants::STABLE_FRAME_TOLERANCE,
+                    *constants::STABLE_FRAME_MAX_WAIT,
+                )? {
+                    warn!(
+                        "Screen still changing after {:?}, solving anyway",
+                        *constants::STABLE_FRAME_MAX_WAIT
+                    );
+                }
+                let solution = self.solve_regions(&screenshots[0], &screenshots[1..])?;
+
+                info!("Fingerprint index: {}", solution.fingerprint_index + 1);
+                debug!(
+                    "pos to check: {:?} (scores: {:?}, total: {}, margin: {})",
+                    solution.slots, solution.part_scores, solution.total_score, solution.margin
+                );
+                if let Err(err) = self.check(&solution) {
+                    warn!(
+                        "{}, capturing again (fingerprint scores: {:?}, part scores: {:?})",
+                        err, solution.fingerprint_scores, solution.part_scores
+                    );
+                    thread_status.sleep(*constants::LOOP_DELAY);
+                    continue;
+                }
+                input.sequence(&selection_keys(&solution.slots))?;
+                self.fix_selection(
+                    input,
+                    frames,
+                    thread_status,
+                    &screenshots[1..],
+                    &solution.slots,
+                )?;
+                input.press(Key::Tab)?;
+                info!("Validating fingerprint...");
+                let outcome = outcome::wait_for_outcome(
+                    frames,
+                    thread_status,
+                    &result_regions,
+                    *constants::CASINO_WAIT_DELAY - *constants::LOOP_DELAY,
+                    |crops| self.result_screen(crops, solution.fingerprint_index),
+                )?;
+                attempts.record(outcome);
+                info!("Fingerprint outcome: {:?} ({})", outcome, attempts);
+                if once {
+                    break;
+                }
+            }
+
+            thread_status.sleep(*constants::LOOP_DELAY);
+        }
+        Ok(attempts)
+    }
+
+    /// Checks which slots the game shows as selected after typing the
+    /// `selection_keys` of `slots`, toggling the wrong ones until they match
+    /// or `CASINO_SELECTION_FIXES` rounds of corrections went by. `before`
+    /// are the slots captured before selecting. Returns whether the selection
+    /// was seen matching `slots`.
+    pub fn fix_selection(
+        &self,
+        input: &mut dyn InputBackend,
+        frames: &mut dyn FrameSource,
+        thread_status: &ThreadStatus,
+        before: &[RgbImage],
+        slots: &[usize],
+    ) -> Result<bool, TaskError> {
+        let mut planned = slots.to_vec();
+        planned.sort();
+        // the selection ends on the last slot in grid order
+        let mut cursor = planned.last().copied().unwrap_or(0);
+        let mut after = Vec::new();
+        for round in 0..=*constants::CASINO_SELECTION_FIXES {
+            if !thread_status.sleep(*constants::CASINO_SELECTION_DELAY) {
+                return Err(TaskError::Cancelled);
+            }
+            if frames.is_exhausted() {
+                break;
+            }
+            frames.next_frame()?;
+            frames.capture_regions_into(&self.parts_pos, &mut after)?;
+            let selected = selected_slots(before, &after, self.selected_change);
+            let (keys, next_cursor) = correction_keys(&planned, &selected, cursor);
+            if keys.is_empty() {
+                return Ok(true);
+            }
+            if round == *constants::CASINO_SELECTION_FIXES {
+                warn!(
+                    "Slots {:?} are selected instead of {:?}, validating anyway",
+                    selected, planned
+                );
+                break;
+            }
+            warn!(
+                "Slots {:?} are selected instead of {:?}, correcting",
+                selected, planned
+            );
+            input.sequence(&keys)?;
+            cursor = next_cursor;
+        }
+        Ok(false)
+    }
+
+    pub fn solve(&self, frame: &RgbImage) -> Result<CasinoSolution, TaskError> {
+        let (fingerprint, parts) = self.crop_solve_regions(frame)?;
+        self.solve_regions(&fingerprint, &parts)
+    }
+
+    /// Solves from the fingerprint and the 8 part slots already cropped out of
+    /// the screen.
+    pub fn solve_regions(
+        &self,
+        fingerprint: &RgbImage,
+        parts: &[RgbImage],
+    ) -> Result<CasinoSolution, TaskError> {
+        let (fingerprint_scores, fingerprint_match, slots) =
+            self.match_fingerprint(fingerprint, parts)?;
+
+        let scores: Vec<Vec<f64>> = self.references.parts[fingerprint_match.index]
+            .iter()
+            .map(|solution| matcher::compare(self.matcher.as_ref(), solution, &slots))
+            .collect::<Result<_, _>>()?;
+        Ok(solution_from_scores(fingerprint_scores, scores))
+    }
+}
+
+/// Picks the slots of the best matching fingerprint from the similarity
+/// between each of its parts and each slot.
+fn solution_from_scores(fingerprint_scores: Vec<f64>, scores: Vec<Vec<f64>>) -> CasinoSolution {
+    let fingerprint_match = utils::best_match(&fingerprint_scores);
+    // parts are matched together so that two of them never pick the
+    // same slot
+    let assignment = best_assignment(&scores);
+    let part_scores = scores
+        .iter()
+        .zip(&assignment.columns)
+        .map(|(part, slot)| part[*slot])
+        .collect();
+
+    CasinoSolution {
+        fingerprint_index: fingerprint_match.index,
+        fingerprint_score: fingerprint_match.score,
+        fingerprint_second: fingerprint_match.second,
+        fingerprint_scores,
+        keys: plan_keys(&assignment.columns),
+        total_score: assignment.total,
+        margin: assignment.set_margin(),
+        slots: assignment.columns,
+        part_margins: assignment.row_margins,
+        part_scores,
+        scores,
+    }
+}
+
+pub fn solve_casino(frame: &RgbImage, resolution: Resolution) -> Result<CasinoSolution, TaskError> {
+    CasinoSolver::new(resolution)?.solve(frame)
+}
+
+/// Keys selecting every slot, starting from the top left one, then validating.
+pub fn plan_keys(slots: &[usize]) -> Vec<Key> {
+    let mut keys = selection_keys(slots);
+    keys.push(Key::Tab);
+    keys
+}
+
+/// Keys selecting every slot, starting from the top left one, leaving the
+/// cursor on the last slot in grid order.
+pub fn selection_keys(slots: &[usize]) -> Vec<Key> {
+    let mut slots = slots.to_vec();
+    slots.sort();
+    let mut keys = Vec::new();
+    let mut cursor = 0;
+    for slot in slots {
+        keys.extend(move_keys(cursor, slot));
+        keys.push(Key::Enter);
+        cursor = slot;
+    }
+    keys
+}
+
+/// Keys moving the cursor from slot `from` to slot `to`. The cursor goes
+/// through the grid row by row, so a right move from the second column lands
+/// on the next row.
+pub fn move_keys(from: usize, to: usize) -> Vec<Key> {
+    let (forward, vertical, distance) = if to >= from {
+        (Key::Right, Key::Down, to - from)
+    } else {
+        (Key::Left, Key::Up, from - to)
+    };
+    let mut keys = vec![forward; distance % 2];
+    keys.extend(vec![vertical; distance / 2]);
+    keys
+}
+
+/// Slots that changed more than `threshold` between `before` and `after`,
+/// crops of the 8 slots taken before and after selecting. Selected slots are
+/// highlighted while the cursor frame only covers the edges, which are left
+/// out.
+pub fn selected_slots(before: &[RgbImage], after: &[RgbImage], threshold: f64) -> Vec<usize> {
+    before
+        .iter()
+        .zip(after)
+        .enumerate()
+        .filter(|(_, (before, after))| slot_change(before, after) > threshold)
+        .map(|(slot, _)| slot)
+        .collect()
+}
+
+/// Mean difference between the centers of two crops of a slot, from 0 (same)
+/// to 1.
+pub fn slot_change(before: &RgbImage, after: &RgbImage) -> f64 {
+    if before.dimensions() != after.dimensions() {
+        return 1.0;
+    }
+    let (width, height) = before.dimensions();
+    let (margin_x, margin_y) = (width / SLOT_MARGIN, height / SLOT_MARGIN);
+    let center = |image| {
+        imageops::crop_imm(
+            image,
+            margin_x,
+            margin_y,
+            width - 2 * margin_x,
+            height - 2 * margin_y,
+        )
+        .to_image()
+    };
+    utils::mean_difference(&center(before), &center(after))
+}
+
+/// Part of a slot width and height on each side left to the cursor frame.
+const SLOT_MARGIN: u32 = 6;
+
+/// Keys toggling every slot whose selection differs from `planned`, starting
+/// from `cursor`. Returns them with the slot the cursor ends on.
+pub fn correction_keys(planned: &[usize], selected: &[usize], cursor: usize) -> (Vec<Key>, usize) {
+    let mut wrong: Vec<usize> = planned
+        .iter()
+        .filter(|slot| !selected.contains(slot))
+        .chain(selected.iter().filter(|slot| !planned.contains(slot)))
+        .copied()
+        .collect();
+    wrong.sort();
+    // visit the wrong slots from the nearest end of the grid
+    if let (Some(first), Some(last)) = (wrong.first(), wrong.last()) {
+        if cursor.abs_diff(*last) < cursor.abs_diff(*first) {
+            wrong.reverse();
+        }
+    }
+    let mut keys = Vec::new();
+    let mut cursor = cursor;
+    for slot in wrong {
+        keys.extend(move_keys(cursor, slot));
+        keys.push(Key::Enter);
+        cursor = slot;
+    }
+    (keys, cursor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::R1080;
+    use image::imageops;
+    use std::path::Path;
+
+    #[test]
+    fn plan_keys_walks_the_grid_in_order() {
+        assert_eq!(
+            plan_keys(&[7, 0, 4, 3]),
+            vec![
+                Key::Enter,
+                Key::Right,
+                Key::Down,
+                Key::Enter,
+                Key::Right,
+                Key::Enter,
+                Key::Right,
+                Key::Down,
+                Key::Enter,
+                Key::Tab,
+            ]
+        );
+    }
+
+    #[test]
+    fn move_keys_wrap_between_rows() {
+        assert_eq!(move_keys(2, 2), vec![]);
+        assert_eq!(move_keys(1, 2), vec![Key::Right]);
+        assert_eq!(move_keys(0, 5), vec![Key::Right, Key::Down, Key::Down]);
+        assert_eq!(move_keys(7, 2), vec![Key::Left, Key::Up, Key::Up]);
+        assert_eq!(move_keys(6, 2), vec![Key::Up, Key::Up]);
+    }
+
+    #[test]
+    fn look_alike_parts_trade_slots_without_ambiguity() {
+        let thresholds = Thresholds::embedded().unwrap().casino;
+        let layout = Layouts::embedded().unwrap().get(R1080).casino;
+        let solver = CasinoSolver::with_layout(R1080, layout, thresholds).unwrap();
+        // part i shows in slot 2 * i, parts 0 and 1 look alike
+        let mut scores = vec![vec![0.3; 8]; 4];
+        for (part, row) in scores.iter_mut().enumerate() {
+            row[2 * part] = 0.95;
+        }
+        scores[0][2] = 0.94;
+        scores[1][0] = 0.94;
+        let solution = solution_from_scores(vec![0.99, 0.5, 0.5, 0.5], scores.clone());
+        assert_eq!(solution.slots, vec![0, 2, 4, 6]);
+        assert!(solution.part_margins[0] < thresholds.part_margin);
+        solver.check(&solution).unwrap();
+
+        // another slot fits a part almost as well
+        scores[3][7] = 0.94;
+        let solution = solution_from_scores(vec![0.99, 0.5, 0.5, 0.5], scores);
+        assert!(matches!(
+            solver.check(&solution),
+            Err(SolveError::AmbiguousSlots { .. })
+        ));
+    }
+
+    #[test]
+    fn corrections_toggle_every_wrong_slot() {
+        // slot 4 was dropped and slot 5 selected by mistake
+        let (keys, cursor) = correction_keys(&[0, 3, 4, 6], &[0, 3, 5, 6], 6);
+        assert_eq!(keys, vec![Key::Left, Key::Enter, Key::Left, Key::Enter]);
+        assert_eq!(cursor, 4);
+        assert_eq!(correction_keys(&[1, 2], &[1, 2], 2), (vec![], 2));
+    }
+
+    #[test]
+    fn solves_a_frame_built_from_the_assets() {
+        let solver = CasinoSolver::new(R1080).unwrap();
+        let asset_folder = Path::new("assets/1080/casino/2");
+        let mut frame = RgbImage::new(R1080.0, R1080.1);
+        let fingerprint_pos = solver.fingerprint_pos;
+        imageops::replace(
+            &mut frame,
+            &utils::load_image(asset_folder.join("full.png")).unwrap(),
+            fingerprint_pos[0] as i64,
+            fingerprint_pos[1] as i64,
+        );
+        for (part, slot) in [(1, 6), (2, 1), (3, 3), (4, 4)] {
+            let region = solver.parts_pos[slot];
+            imageops::replace(
+                &mut frame,
+                &utils::load_image(asset_folder.join(format!("{}.png", part))).unwrap(),
+                region[0] as i64,
+                region[1] as i64,
+            );
+        }
+
+        let solution = solve_casino(&frame, R1080).unwrap();
+        assert_eq!(solution.fingerprint_index, 1);
+        assert_eq!(solution.slots, vec![6, 1, 3, 4]);
+        assert_eq!(solution.keys, plan_keys(&[1, 3, 4, 6]));
+    }
+}
@@ -0,0 +1,397 @@
+use super::assignment::best_assignment;
+use super::outcome::{self, Attempts};
+use super::{FingerprintSolver, Margins, SolveError, TaskAssets, check_thresholds, crop_features};
+use crate::capture::{self, FrameSource};
+use crate::constants;
+use crate::error::TaskError;
+use crate::input::{InputBackend, Key};
+use crate::layout::{Layouts, TaskLayout};
+use crate::matcher;
+use crate::screen::{ScreenState, ScreenWatcher};
+use crate::thresholds::{TaskThresholds, Thresholds};
+use crate::utils::{self, Match, Resolution, ThreadStatus};
+use image::RgbImage;
+use log::{debug, info, warn};
+use std::ops::Deref;
+
+pub const ROW_COUNT: usize = 8;
+
+/// Reference images of the cayo fingerprints.
+pub fn assets() -> TaskAssets {
+    TaskAssets {
+        name: "cayo",
+        fingerprint_count: *constants::CAYO_FINGERPRINT_COUNT as usize,
+        fingerprint_file: "fingerprint.png",
+        part_count: ROW_COUNT,
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CayoSolution {
+    pub fingerprint_index: usize,
+    pub fingerprint_score: f64,
+    /// Similarity of the second best matching fingerprint.
+    pub fingerprint_second: f64,
+    /// Similarity between the screen fingerprint and each known one.
+    pub fingerprint_scores: Vec<f64>,
+    /// Part currently shown by each row, the puzzle is solved when row `i`
+    /// shows part `i`.
+    pub current_indices: Vec<usize>,
+    /// Similarity between each row and the part it was matched with.
+    pub row_scores: Vec<f64>,
+    /// How much the picked parts beat the best ones giving each row another
+    /// part.
+    pub row_margins: Vec<f64>,
+    /// Similarity between each row and each of the 8 parts.
+    pub scores: Vec<Vec<f64>>,
+    pub keys: Vec<Key>,
+}
+
+/// How rotating a row to its part went.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RowAlignment {
+    /// Seen showing its part after `attempts` rotations, 0 when the solve
+    /// already saw it there.
+    Aligned { attempts: usize },
+    /// Rotated `attempts` times but not recognized with enough confidence
+    /// afterwards, or no frame was left to check it.
+    Unchecked { attempts: usize },
+    /// Still showing `part` after the last of `attempts` rotations.
+    Misaligned { attempts: usize, part: usize },
+}
+
+pub struct CayoSolver {
+    core: FingerprintSolver,
+}
+
+impl Deref for CayoSolver {
+    type Target = FingerprintSolver;
+
+    fn deref(&self) -> &FingerprintSolver {
+        &self.core
+    }
+}
+
+impl CayoSolver {
+    /// Solver using the layout of `resolution` and the thresholds from the
+    /// embedded and user configuration files.
+    pub fn new(resolution: Resolution) -> Result<Self, TaskError> {
+        let layout = Layouts::load()?.get(resolution).cayo;
+        Self::with_layout(resolution, layout, Thresholds::load()?.cayo)
+    }
+
+    pub fn with_layout(
+        resolution: Resolution,
+        layout: TaskLayout,
+        thresholds: TaskThresholds,
+    ) -> Result<Self, TaskError> {
+        Ok(CayoSolver {
+            core: FingerprintSolver::new(resolution, layout, thresholds, &assets())?,
+        })
+    }
+
+    /// Fails when `solution` is not confident enough to send its keys, e.g.
+    /// when another part fits a row almost as well and the puzzle would
+    /// likely be left unsolved.
+    pub fn check(&self, solution: &CayoSolution) -> Result<(), SolveError> {
+        check_thresholds(
+            &self.thresholds,
+            solution.fingerprint_score,
+            solution.fingerprint_second,
+            &solution.row_scores,
+            Margins::PerPart(&solution.row_margins),
+        )
+    }
+
+    pub fn solve(&self, frame: &RgbImage) -> Result<CayoSolution, TaskError> {
+        let (fingerprint, rows) = self.crop_solve_regions(frame)?;
+        self.solve_regions(&fingerprint, &rows)
+    }
+
+    /// Solves from the fingerprint and the 8 rows already cropped out of the
+    /// screen.
+    pub fn solve_regions(
+        &self,
+        fingerprint: &RgbImage,
+        rows: &[RgbImage],
+    ) -> Result<CayoSolution, TaskError> {
+        let (fingerprint_scores, fingerprint_match, rows) =
+            self.match_fingerprint(fingerprint, rows)?;
+        let fingerprint_index = fingerprint_match.index;
+        let curr_parts = &self.references.parts[fingerprint_index];
+
+        let scores: Vec<Vec<f64>> = rows
+            .iter()
+            .map(|row| matcher::compare(self.matcher.as_ref(), row, curr_parts))
+            .collect::<Result<_, _>>()?;
+        // every part is shown by exactly one row
+        let assignment = best_assignment(&scores);
+        let current_indices = assignment.columns;
+        let row_scores = current_indices
+            .iter()
+            .zip(&scores)
+            .map(|(index, row)| row[*index])
+            .collect();
+
+        Ok(CayoSolution {
+            fingerprint_index,
+            fingerprint_score: fingerprint_match.score,
+            fingerprint_second: fingerprint_match.second,
+            fingerprint_scores,
+            keys: plan_keys(&current_indices),
+            current_indices,
+            row_scores,
+            row_margins: assignment.row_margins,
+            scores,
+        })
+    }
+
+    /// Solves every cayo fingerprint `screen` sees on `frames` until the
+    /// task is stopped, the frames run out, or the first one was submitted
+    /// when `once` is set. Returns how the submitted fingerprints went.
+    pub fn run(
+        &self,
+        screen: &mut ScreenWatcher,
+        input: &mut dyn InputBackend,
+        frames: &mut dyn FrameSource,
+        thread_status: &ThreadStatus,
+        once: bool,
+    ) -> Result<Attempts, TaskError> {
+        let solve_regions = self.solve_regions_pos();
+        let result_regions = self.result_regions_pos();
+        let mut attempts = Attempts::default();
+        let fingerprints = screen.subscribe(ScreenState::CayoFingerprint);
+        // crops are reused from frame to frame
+        let mut screenshots = Vec::new();
+
+        loop {
+            if !thread_status.is_running() || frames.is_exhausted() {
+                break;
+            };
+            frames.next_frame()?;
+
+            if let Some(transition) = screen.update(frames)? {
+                debug!(
+                    "Screen changed from {:?} to {:?}",
+                    transition.from, transition.to
+                );
+            }
+            if fingerprints.try_iter().last().is_some() {
+                // reported again on the next frame if it is still shown,
+                // to capture it again or solve the next one
+                screen.reset();
+                info!("Fingerprint detected");
+                // the fingerprint may still be animating in
+                if !capture::wait_until_stable(
+                    frames,
+                    thread_status,
+                    &solve_regions,
+                    &mut screenshots,
+                    *constants::STABLE_FRAME_TOLERANCE,
+                    *constants::STABLE_FRAME_MAX_WAIT,
+                )? {
+                    warn!(
+                        "Screen still changing after {:?}, solving anyway",
+                        *constants::STABLE_FRAME_MAX_WAIT
+                    );
+                }
+                let solution = self.solve_regions(&screenshots[0], &screenshots[1..])?;
+                info!("Fingerprint index: {}", solution.fingerprint_index);
+                for (i, ((part_index, score), margin)) in solution
+                    .current_indices
+                    .iter()
+                    .zip(&solution.row_scores)
+                    .zip(&solution.row_margins)
+                    .enumerate()
+                {
+                    debug!(
+                        "part n°{} : current index: {}, target index : {} (score: {}, margin: {})",
+                        i, part_index, i, score, margin
+                    );
+                }
+                if let Err(err) = self.check(&solution) {
+                    warn!(
+                        "{}, capturing again (fingerprint scores: {:?}, row scores: {:?})",
+                        err, solution.fingerprint_scores, solution.row_scores
+                    );
+                    thread_status.sleep(*constants::LOOP_DELAY);
+                    continue;
+                }
+
+                self.align_rows(input, frames, thread_status, &solution)?;
+                let outcome = outcome::wait_for_outcome(
+                    frames,
+                    thread_status,
+                    &result_regions,
+                    *constants::CAYO_WAIT_DELAY - *constants::LOOP_DELAY,
+                    |crops| self.result_screen(crops, solution.fingerprint_index),
+                )?;
+                attempts.record(outcome);
+                info!("Fingerprint outcome: {:?} ({})", outcome, attempts);
+                if once {
+                    break;
+                }
+            }
+
+            thread_status.sleep(*constants::LOOP_DELAY);
+        }
+        Ok(attempts)
+    }
+
+    /// Rotates every row to its part with the keys of `solution`, capturing
+    /// the row again after each rotation and rotating it again while it is
+    /// confidently seen showing another part, up to `CAYO_ROW_ATTEMPTS`
+    /// times. Goes down to the next row after each one.
+    pub fn align_rows(
+        &self,
+        input: &mut dyn InputBackend,
+        frames: &mut dyn FrameSource,
+        thread_status: &ThreadStatus,
+        solution: &CayoSolution,
+    ) -> Result<Vec<RowAlignment>, TaskError> {
+        let mut row_screen = Vec::new();
+        let mut alignments = Vec::with_capacity(ROW_COUNT);
+        for (row, current) in solution.current_indices.iter().enumerate() {
+            let mut current = *current;
+            let mut attempts = 0;
+            let mut checked = true;
+            while current != row && attempts < *constants::CAYO_ROW_ATTEMPTS {
+                input.sequence(&row_moves(current, row))?;
+                attempts += 1;
+                if !thread_status.sleep(*constants::CAYO_ROW_DELAY) {
+                    return Err(TaskError::Cancelled);
+                }
+                if frames.is_exhausted() {
+                    checked = false;
+                    break;
+                }
+                frames.next_frame()?;
+                frames.capture_regions_into(&self.parts_pos[row..=row], &mut row_screen)?;
+                let part = self.row_part(solution.fingerprint_index, &row_screen[0])?;
+                debug!(
+                    "row n°{} shows part {} (score: {}, margin: {})",
+                    row,
+                    part.index,
+                    part.score,
+                    part.margin()
+                );
+                // an unsure read is no reason to rotate again
+                if part.score < self.thresholds.part || part.margin() < self.thresholds.part_margin
+                {
+                    checked = false;
+                    break;
+                }
+                current = part.index;
+            }
+            let alignment = if !checked {
+                info!("Row n°{} rotated {} time(s), not checked", row, attempts);
+                RowAlignment::Unchecked { attempts }
+            } else if current == row {
+                info!("Row n°{} aligned after {} attempt(s)", row, attempts);
+                RowAlignment::Aligned { attempts }
+            } else {
+                warn!(
+                    "Row n°{} still shows part {} after {} attempts, moving on",
+                    row, current, attempts
+                );
+                RowAlignment::Misaligned {
+                    attempts,
+                    part: current,
+                }
+            };
+            alignments.push(alignment);
+            input.press(Key::Down)?;
+        }
+        Ok(alignments)
+    }
+
+    /// Part of fingerprint `fingerprint_index` a single row crop shows, to
+    /// check a row after rotating it.
+    pub fn row_part(&self, fingerprint_index: usize, row: &RgbImage) -> Result<Match, TaskError> {
+        let features = crop_features(self.matcher.as_ref(), &[row], self.scale);
+        Ok(utils::best_match(&matcher::compare(
+            self.matcher.as_ref(),
+            &features[0],
+            &self.references.parts[fingerprint_index],
+        )?))
+    }
+}
+
+pub fn solve_cayo(frame: &RgbImage, resolution: Resolution) -> Result<CayoSolution, TaskError> {
+    CayoSolver::new(resolution)?.solve(frame)
+}
+
+/// Keys rotating every row to its target part, going down after each one.
+pub fn plan_keys(current_indices: &[usize]) -> Vec<Key> {
+    let mut keys = Vec::new();
+    for (target, current) in current_indices.iter().enumerate() {
+        keys.extend(row_moves(*current, target));
+        keys.push(Key::Down);
+    }
+    keys
+}
+
+/// Shortest rotation from `current` to `target`, rows wrap around.
+pub fn row_moves(current: usize, target: usize) -> Vec<Key> {
+    if current == target {
+        return Vec::new();
+    }
+
+    if target > current {
+        if target - current > 4 {
+            vec![Key::Left; ROW_COUNT - target + current]
+        } else {
+            vec![Key::Right; target - current]
+        }
+    } else if current - target > 4 {
+        vec![Key::Right; ROW_COUNT - current + target]
+    } else {
+        vec![Key::Left; current - target]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::R1080;
+    use image::imageops;
+    use std::path::Path;
+
+    #[test]
+    fn row_moves_takes_the_shortest_way() {
+        assert_eq!(row_moves(3, 3), vec![]);
+        assert_eq!(row_moves(1, 3), vec![Key::Right; 2]);
+        assert_eq!(row_moves(0, 7), vec![Key::Left]);
+        assert_eq!(row_moves(5, 2), vec![Key::Left; 3]);
+        assert_eq!(row_moves(7, 1), vec![Key::Right; 2]);
+    }
+
+    #[test]
+    fn solves_a_frame_built_from_the_assets() {
+        let solver = CayoSolver::new(R1080).unwrap();
+        let asset_folder = Path::new("assets/1080/cayo/3");
+        let mut frame = RgbImage::new(R1080.0, R1080.1);
+        let fingerprint_pos = solver.fingerprint_pos;
+        imageops::replace(
+            &mut frame,
+            &utils::load_image(asset_folder.join("fingerprint.png")).unwrap(),
+            fingerprint_pos[0] as i64,
+            fingerprint_pos[1] as i64,
+        );
+        let shown = [2, 0, 7, 3, 4, 1, 6, 5];
+        for (row, part) in shown.iter().enumerate() {
+            let region = solver.parts_pos[row];
+            imageops::replace(
+                &mut frame,
+                &utils::load_image(asset_folder.join(format!("{}.png", part + 1))).unwrap(),
+                region[0] as i64,
+                region[1] as i64,
+            );
+        }
+
+        let solution = solve_cayo(&frame, R1080).unwrap();
+        assert_eq!(solution.fingerprint_index, 2);
+        assert_eq!(solution.current_indices, shown.to_vec());
+        assert_eq!(solution.keys, plan_keys(&shown));
+    }
+}
@@ -0,0 +1,167 @@
+use super::{ReferenceFeatures, TaskAssets};
+use crate::error::TaskError;
+use crate::matcher::{self, MatcherConfig};
+use crate::utils::{self, Asset};
+use image::RgbImage;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// How well a matcher recognizes the reference images of a task made for one
+/// resolution among the ones made for another.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Evaluation {
+    pub fingerprints: usize,
+    pub fingerprint_hits: usize,
+    pub parts: usize,
+    pub part_hits: usize,
+    /// Smallest lead of the right match over the best wrong one, negative when
+    /// a wrong one won.
+    pub worst_margin: f64,
+    /// Time taken to process and compare one screen image.
+    pub time_per_image: Duration,
+}
+
+impl Evaluation {
+    pub fn fingerprint_accuracy(&self) -> f64 {
+        self.fingerprint_hits as f64 / self.fingerprints as f64
+    }
+
+    pub fn part_accuracy(&self) -> f64 {
+        self.part_hits as f64 / self.parts as f64
+    }
+}
+
+/// Matches the images of every resolution with embedded assets against the
+/// references of every other one, rescaled like screen crops are.
+pub fn evaluate(config: &MatcherConfig, assets: &TaskAssets) -> Result<Evaluation, TaskError> {
+    let matcher = config.build()?;
+    let heights = asset_heights(assets);
+    if heights.len() < 2 {
+        return Err(TaskError::AssetMissing(format!(
+            "{} images for two resolutions",
+            assets.name
+        )));
+    }
+
+    let mut evaluation = Evaluation {
+        fingerprints: 0,
+        fingerprint_hits: 0,
+        parts: 0,
+        part_hits: 0,
+        worst_margin: f64::INFINITY,
+        time_per_image: Duration::ZERO,
+    };
+    let mut elapsed = Duration::ZERO;
+    for reference_height in &heights {
+        let references =
+            ReferenceFeatures::load(matcher.as_ref(), &folder(*reference_height, assets), assets)?;
+        for query_height in heights.iter().filter(|height| *height != reference_height) {
+            let scale = *reference_height as f64 / *query_height as f64;
+            let query_folder = folder(*query_height, assets);
+            for fingerprint in 0..assets.fingerprint_count {
+                let fingerprint_folder = query_folder.join((fingerprint + 1).to_string());
+                let mut images = vec![load(
+                    fingerprint_folder.join(assets.fingerprint_file),
+                    scale,
+                )?];
+                for part in 1..=assets.part_count {
+                    images.push(load(
+                        fingerprint_folder.join(format!("{}.png", part)),
+                        scale,
+                    )?);
+                }
+
+                let start = Instant::now();
+                let features = matcher::features_of(matcher.as_ref(), &images);
+                let mut results = vec![(
+                    fingerprint,
+                    matcher::compare(matcher.as_ref(), &features[0], &references.fingerprints)?,
+                )];
+                for (part, part_features) in features[1..].iter().enumerate() {
+                    results.push((
+                        part,
+                        matcher::compare(
+                            matcher.as_ref(),
+                            part_features,
+                            &references.parts[fingerprint],
+                        )?,
+                    ));
+                }
+                elapsed += start.elapsed();
+
+                for (i, (expected, scores)) in results.iter().enumerate() {
+                    let best = utils::best_match(scores);
+                    let wrong = scores
+                        .iter()
+                        .enumerate()
+                        .filter(|(index, _)| index != expected)
+                        .map(|(_, score)| *score)
+                        .fold(f64::NEG_INFINITY, f64::max);
+                    let hit = best.index == *expected;
+                    evaluation.worst_margin =
+                        evaluation.worst_margin.min(scores[*expected] - wrong);
+                    if i == 0 {
+                        evaluation.fingerprints += 1;
+                        evaluation.fingerprint_hits += hit as usize;
+                    } else {
+                        evaluation.parts += 1;
+                        evaluation.part_hits += hit as usize;
+                    }
+                }
+            }
+        }
+    }
+    evaluation.time_per_image =
+        elapsed / (evaluation.fingerprints + evaluation.parts).max(1) as u32;
+    Ok(evaluation)
+}
+
+/// Heights of the resolutions with embedded images for `assets`.
+fn asset_heights(assets: &TaskAssets) -> Vec<u32> {
+    let mut heights: Vec<u32> = Asset::iter()
+        .filter_map(|path| {
+            let mut components = path.split('/').skip(1);
+            let height = components.next()?.parse().ok()?;
+            (components.next() == Some(assets.name)).then_some(height)
+        })
+        .collect();
+    heights.sort();
+    heights.dedup();
+    heights
+}
+
+fn folder(height: u32, assets: &TaskAssets) -> PathBuf {
+    Path::new("assets")
+        .join(height.to_string())
+        .join(assets.name)
+}
+
+fn load(path: PathBuf, scale: f64) -> Result<RgbImage, TaskError> {
+    Ok(utils::rescale(&utils::load_image(path)?, scale).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solver::{casino, cayo};
+    use crate::thresholds::Thresholds;
+
+    #[test]
+    fn default_matchers_recognize_every_asset() {
+        let thresholds = Thresholds::embedded().unwrap();
+        for (config, assets) in [
+            (thresholds.casino.matcher, casino::assets()),
+            (thresholds.cayo.matcher, cayo::assets()),
+        ] {
+            let evaluation = evaluate(&config, &assets).unwrap();
+            assert_eq!(evaluation.fingerprints, 2 * assets.fingerprint_count);
+            assert_eq!(
+                evaluation.parts,
+                2 * assets.fingerprint_count * assets.part_count
+            );
+            assert_eq!(evaluation.fingerprint_accuracy(), 1.0, "{}", assets.name);
+            assert_eq!(evaluation.part_accuracy(), 1.0, "{}", assets.name);
+            assert!(evaluation.worst_margin > 0.0, "{}", assets.name);
+        }
+    }
+}
@@ -0,0 +1,405 @@
+use crate::error::TaskError;
+use crate::layout::{self, REFERENCE_RESOLUTION, TaskLayout};
+use crate::matcher::{self, Features, Matcher};
+use crate::thresholds::TaskThresholds;
+use crate::utils::{self, Asset, Match, Region, Resolution};
+use image::RgbImage;
+use log::warn;
+use outcome::ResultScreen;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+pub mod assignment;
+pub mod casino;
+pub mod cayo;
+pub mod evaluation;
+pub mod outcome;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SolveError {
+    FrameSizeMismatch {
+        expected: Resolution,
+        actual: Resolution,
+    },
+    /// No known fingerprint is similar enough to the screen one, or two of
+    /// them are.
+    UnsureFingerprint { score: f64, second: f64 },
+    /// The part matched for `part` (0 based) is not similar enough to it.
+    UnsurePart { part: usize, score: f64 },
+    /// Another match fits `part` (0 based) almost as well as the picked one.
+    Ambiguous { part: usize, margin: f64 },
+    /// Other slots fit the casino parts almost as well as the picked ones.
+    AmbiguousSlots { margin: f64 },
+}
+
+impl fmt::Display for SolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SolveError::FrameSizeMismatch { expected, actual } => write!(
+                f,
+                "Frame is {}x{} but {}x{} was expected",
+                actual.0, actual.1, expected.0, expected.1
+            ),
+            SolveError::UnsureFingerprint { score, second } => write!(
+                f,
+                "Unsure fingerprint, best similarity {:.3} and second {:.3}",
+                score, second
+            ),
+            SolveError::UnsurePart { part, score } => {
+                write!(f, "Unsure part {}, best similarity {:.3}", part + 1, score)
+            }
+            SolveError::Ambiguous { part, margin } => write!(
+                f,
+                "Part {} is ambiguous, the best matches are only {:.3} apart",
+                part + 1,
+                margin
+            ),
+            SolveError::AmbiguousSlots { margin } => write!(
+                f,
+                "Ambiguous slots, the best other slots are only {:.3} behind",
+                margin
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SolveError {}
+
+/// Folder holding the reference images of `task` and the factor turning crops
+/// of a `resolution` screen into the size of those images. Images made for the
+/// UI size of `resolution` are used when they exist, the reference resolution
+/// ones otherwise.
+pub fn reference_assets(resolution: Resolution, task: &str) -> (PathBuf, f64) {
+    let ui_height = layout::ui_area(resolution).3;
+    let native_height = ui_height.round() as u32;
+    let asset_height =
+        if Asset::get(&format!("assets/{}/{}/header.png", native_height, task)).is_some() {
+            native_height
+        } else {
+            REFERENCE_RESOLUTION.1
+        };
+    (
+        Path::new("assets")
+            .join(asset_height.to_string())
+            .join(task),
+        asset_height as f64 / ui_height,
+    )
+}
+
+/// Reference images of a fingerprint task.
+pub struct TaskAssets {
+    /// Folder of the task in every resolution folder.
+    pub name: &'static str,
+    pub fingerprint_count: usize,
+    /// Image of the whole fingerprint in every numbered fingerprint folder.
+    pub fingerprint_file: &'static str,
+    /// Parts of every fingerprint, numbered from 1.
+    pub part_count: usize,
+}
+
+/// Features of the reference images of a task, computed once when its solver
+/// is created so that only screen crops are processed on every frame.
+pub struct ReferenceFeatures {
+    pub header: Features,
+    pub fingerprints: Vec<Features>,
+    /// Parts of each fingerprint.
+    pub parts: Vec<Vec<Features>>,
+    /// Success and failure screens, for the ones with a reference image.
+    pub results: Vec<(ResultScreen, Features)>,
+}
+
+impl ReferenceFeatures {
+    /// Features of the header and of every fingerprint and part of `assets`
+    /// in `folder`.
+    pub fn load(
+        matcher: &dyn Matcher,
+        folder: &Path,
+        assets: &TaskAssets,
+    ) -> Result<Self, TaskError> {
+        let header = matcher.features(&utils::load_image(folder.join("header.png"))?);
+        let mut fingerprints = Vec::with_capacity(assets.fingerprint_count);
+        let mut parts = Vec::with_capacity(assets.fingerprint_count);
+        for fingerprint in 1..=assets.fingerprint_count {
+            let fingerprint_folder = folder.join(fingerprint.to_string());
+            let mut images = vec![utils::load_image(
+                fingerprint_folder.join(assets.fingerprint_file),
+            )?];
+            for part in 1..=assets.part_count {
+                images.push(utils::load_image(
+                    fingerprint_folder.join(format!("{}.png", part)),
+                )?);
+            }
+            let mut features = matcher::features_of(matcher, &images);
+            fingerprints.push(features.remove(0));
+            parts.push(features);
+        }
+        let mut results = Vec::new();
+        for (screen, file) in [
+            (ResultScreen::Success, "success.png"),
+            (ResultScreen::Failure, "failure.png"),
+        ] {
+            match utils::load_image(folder.join(file)) {
+                Ok(image) => results.push((screen, matcher.features(&image))),
+                Err(TaskError::AssetMissing(_)) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(ReferenceFeatures {
+            header,
+            fingerprints,
+            parts,
+            results,
+        })
+    }
+}
+
+/// Rescales screen crops to the size of the reference images and computes
+/// their features.
+fn crop_features(matcher: &dyn Matcher, crops: &[&RgbImage], scale: f64) -> Vec<Features> {
+    let crops: Vec<RgbImage> = crops
+        .iter()
+        .map(|crop| utils::rescale(crop, scale).into_owned())
+        .collect();
+    matcher::features_of(matcher, &crops)
+}
+
+/// Part of the casino and cayo solvers shared by both: the layout of the
+/// task, the features of its reference images and the matcher comparing
+/// screen crops with them. Each solver derefs to it and only adds the
+/// matching of its parts and the keys solving them.
+pub struct FingerprintSolver {
+    resolution: Resolution,
+    header_pos: Region,
+    fingerprint_pos: Region,
+    pub(crate) parts_pos: Vec<Region>,
+    result_pos: Option<Region>,
+    /// Factor turning screen crops into the size of the reference images.
+    pub(crate) scale: f64,
+    pub(crate) thresholds: TaskThresholds,
+    pub(crate) matcher: Box<dyn Matcher>,
+    pub(crate) references: ReferenceFeatures,
+}
+
+impl FingerprintSolver {
+    pub fn new(
+        resolution: Resolution,
+        layout: TaskLayout,
+        thresholds: TaskThresholds,
+        assets: &TaskAssets,
+    ) -> Result<Self, TaskError> {
+        let (asset_folder, scale) = reference_assets(resolution, assets.name);
+
+        let matcher = thresholds.matcher.build()?;
+        let references = ReferenceFeatures::load(matcher.as_ref(), &asset_folder, assets)?;
+        // result screens are only told apart with both, otherwise the tasks
+        // wait for the next fingerprint or their timeout
+        match (layout.result, references.results.is_empty()) {
+            (Some(_), true) => warn!(
+                "The {} layout has a result region but {} has no success.png or failure.png",
+                assets.name,
+                asset_folder.display()
+            ),
+            (None, false) => warn!(
+                "{} has result images but the {} layout has no result region",
+                asset_folder.display(),
+                assets.name
+            ),
+            _ => {}
+        }
+
+        Ok(FingerprintSolver {
+            resolution,
+            header_pos: layout.header,
+            fingerprint_pos: layout.fingerprint,
+            parts_pos: layout.parts,
+            result_pos: layout.result,
+            scale,
+            thresholds,
+            matcher,
+            references,
+        })
+    }
+
+    pub fn resolution(&self) -> Resolution {
+        self.resolution
+    }
+
+    pub fn header_pos(&self) -> &Region {
+        &self.header_pos
+    }
+
+    /// The 8 casino slots, left to right then top to bottom, or the 8 cayo
+    /// rows, top to bottom.
+    pub fn parts_pos(&self) -> &[Region] {
+        &self.parts_pos
+    }
+
+    /// Fingerprint region followed by the 8 parts, in the order expected by
+    /// `solve_regions`.
+    pub fn solve_regions_pos(&self) -> Vec<Region> {
+        let mut regions = vec![self.fingerprint_pos];
+        regions.extend(&self.parts_pos);
+        regions
+    }
+
+    /// Header similarity above which the fingerprint screen is shown.
+    pub fn header_threshold(&self) -> f64 {
+        header_threshold(&self.thresholds, self.scale)
+    }
+
+    pub fn header_similarity(&self, header: &RgbImage) -> Result<f64, TaskError> {
+        let header = self.matcher.features(&utils::rescale(header, self.scale));
+        self.matcher.similarity(&self.references.header, &header)
+    }
+
+    /// Header, fingerprint and result regions, in the order expected by
+    /// `result_screen`. The result region is left out when the layout or the
+    /// reference images lack it.
+    pub fn result_regions_pos(&self) -> Vec<Region> {
+        let mut regions = vec![self.header_pos, self.fingerprint_pos];
+        if !self.references.results.is_empty() {
+            regions.extend(self.result_pos);
+        }
+        regions
+    }
+
+    /// What the header, fingerprint and (optionally) result crops of
+    /// `result_regions_pos` show after fingerprint `previous` was submitted:
+    /// a result screen, or the header with another fingerprint.
+    pub fn result_screen(
+        &self,
+        crops: &[RgbImage],
+        previous: usize,
+    ) -> Result<Option<ResultScreen>, TaskError> {
+        let matcher = self.matcher.as_ref();
+        let features = crop_features(matcher, &crops.iter().collect::<Vec<_>>(), self.scale);
+        if let Some(result) = features.get(2) {
+            let screen = outcome::recognize_result(
+                matcher,
+                &self.references.results,
+                result,
+                self.thresholds.result,
+            )?;
+            if screen.is_some() {
+                return Ok(screen);
+            }
+        }
+        if matcher.similarity(&self.references.header, &features[0])? <= self.header_threshold() {
+            return Ok(None);
+        }
+        let fingerprint = utils::best_match(&matcher::compare(
+            matcher,
+            &features[1],
+            &self.references.fingerprints,
+        )?);
+        Ok(
+            (fingerprint.index != previous && fingerprint.score >= self.thresholds.fingerprint)
+                .then_some(ResultScreen::NextFingerprint),
+        )
+    }
+
+    /// Crops of `solve_regions_pos` out of a whole `frame`.
+    fn crop_solve_regions(&self, frame: &RgbImage) -> Result<(RgbImage, Vec<RgbImage>), TaskError> {
+        if frame.dimensions() != self.resolution {
+            return Err(SolveError::FrameSizeMismatch {
+                expected: self.resolution,
+                actual: frame.dimensions(),
+            }
+            .into());
+        }
+        let fingerprint = utils::crop_region(frame, &self.fingerprint_pos);
+        let parts = self
+            .parts_pos
+            .iter()
+            .map(|region| utils::crop_region(frame, region))
+            .collect();
+        Ok((fingerprint, parts))
+    }
+
+    /// Similarity between the fingerprint crop and each known fingerprint,
+    /// the best of them, and the features of the part crops.
+    fn match_fingerprint(
+        &self,
+        fingerprint: &RgbImage,
+        parts: &[RgbImage],
+    ) -> Result<(Vec<f64>, Match, Vec<Features>), TaskError> {
+        let mut features = crop_features(
+            self.matcher.as_ref(),
+            &[fingerprint].into_iter().chain(parts).collect::<Vec<_>>(),
+            self.scale,
+        );
+        let parts = features.split_off(1);
+        let scores = matcher::compare(
+            self.matcher.as_ref(),
+            &features[0],
+            &self.references.fingerprints,
+        )?;
+        let fingerprint_match = utils::best_match(&scores);
+        Ok((scores, fingerprint_match, parts))
+    }
+}
+
+/// Header similarity above which the fingerprint screen is shown, lower when
+/// crops are rescaled to reference images of another resolution.
+pub(crate) fn header_threshold(thresholds: &TaskThresholds, scale: f64) -> f64 {
+    if scale == 1.0 {
+        thresholds.header
+    } else {
+        thresholds.rescaled_header
+    }
+}
+
+/// Lead of a solution over the matches it was picked against.
+enum Margins<'a> {
+    /// Lead of each part over the best matches giving it another one.
+    PerPart(&'a [f64]),
+    /// Lead of the whole solution over the best one making other choices.
+    Whole(f64),
+}
+
+/// Fails on the first match falling short of `thresholds`: the fingerprint,
+/// then the least certain part (or solution), then the least similar part.
+fn check_thresholds(
+    thresholds: &TaskThresholds,
+    fingerprint_score: f64,
+    fingerprint_second: f64,
+    part_scores: &[f64],
+    margins: Margins,
+) -> Result<(), SolveError> {
+    if fingerprint_score < thresholds.fingerprint
+        || fingerprint_score - fingerprint_second < thresholds.fingerprint_margin
+    {
+        return Err(SolveError::UnsureFingerprint {
+            score: fingerprint_score,
+            second: fingerprint_second,
+        });
+    }
+    match margins {
+        Margins::PerPart(part_margins) => {
+            if let Some((part, margin)) = lowest(part_margins) {
+                if margin < thresholds.part_margin {
+                    return Err(SolveError::Ambiguous { part, margin });
+                }
+            }
+        }
+        Margins::Whole(margin) => {
+            if margin < thresholds.part_margin {
+                return Err(SolveError::AmbiguousSlots { margin });
+            }
+        }
+    }
+    if let Some((part, score)) = lowest(part_scores) {
+        if score < thresholds.part {
+            return Err(SolveError::UnsurePart { part, score });
+        }
+    }
+    Ok(())
+}
+
+fn lowest(values: &[f64]) -> Option<(usize, f64)> {
+    values
+        .iter()
+        .copied()
+        .enumerate()
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+}
@@ -0,0 +1,180 @@
+use crate::capture::FrameSource;
+use crate::constants;
+use crate::error::TaskError;
+use crate::matcher::{Features, Matcher};
+use crate::utils::{Region, ThreadStatus};
+use image::RgbImage;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Screens the game shows after a fingerprint was submitted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResultScreen {
+    Success,
+    /// The fingerprint was wrong and is being decrypted again.
+    Failure,
+    /// Another fingerprint to solve, the previous one was right.
+    NextFingerprint,
+}
+
+/// How a submitted fingerprint went.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Success,
+    Failure,
+    /// Nothing was recognized before the timeout.
+    Unknown,
+}
+
+/// Submitted fingerprints by outcome.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Attempts {
+    pub successes: usize,
+    pub failures: usize,
+    pub unknown: usize,
+}
+
+impl Attempts {
+    pub fn record(&mut self, outcome: Outcome) {
+        match outcome {
+            Outcome::Success => self.successes += 1,
+            Outcome::Failure => self.failures += 1,
+            Outcome::Unknown => self.unknown += 1,
+        }
+    }
+}
+
+impl fmt::Display for Attempts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} solved, {} failed, {} unknown",
+            self.successes, self.failures, self.unknown
+        )
+    }
+}
+
+/// Success or failure screen `crop` shows, among the ones with reference
+/// features in `results`.
+pub(crate) fn recognize_result(
+    matcher: &dyn Matcher,
+    results: &[(ResultScreen, Features)],
+    crop: &Features,
+    threshold: f64,
+) -> Result<Option<ResultScreen>, TaskError> {
+    let mut best = None;
+    for (screen, reference) in results {
+        let similarity = matcher.similarity(reference, crop)?;
+        if similarity > threshold && best.is_none_or(|(_, best)| similarity > best) {
+            best = Some((*screen, similarity));
+        }
+    }
+    Ok(best.map(|(screen, _)| screen))
+}
+
+/// Captures `regions` until `recognize` sees the next fingerprint or a result
+/// screen that went away again, giving up after `timeout`. A next
+/// fingerprint without any result screen counts as a success.
+pub fn wait_for_outcome(
+    frames: &mut dyn FrameSource,
+    thread_status: &ThreadStatus,
+    regions: &[Region],
+    timeout: Duration,
+    recognize: impl Fn(&[RgbImage]) -> Result<Option<ResultScreen>, TaskError>,
+) -> Result<Outcome, TaskError> {
+    let start = Instant::now();
+    let mut crops = Vec::new();
+    let mut outcome = Outcome::Unknown;
+    while start.elapsed() < timeout {
+        if !thread_status.sleep(*constants::LOOP_DELAY) {
+            return Err(TaskError::Cancelled);
+        }
+        if frames.is_exhausted() {
+            break;
+        }
+        frames.next_frame()?;
+        frames.capture_regions_into(regions, &mut crops)?;
+        match recognize(&crops)? {
+            Some(ResultScreen::Success) => outcome = Outcome::Success,
+            Some(ResultScreen::Failure) => outcome = Outcome::Failure,
+            Some(ResultScreen::NextFingerprint) => {
+                if outcome == Outcome::Unknown {
+                    outcome = Outcome::Success;
+                }
+                break;
+            }
+            None if outcome != Outcome::Unknown => break,
+            None => {}
+        }
+    }
+    Ok(outcome)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::ReplaySource;
+    use crate::matcher::MatcherConfig;
+    use image::{Rgb, Rgba, RgbaImage};
+
+    fn frame(shade: u8) -> RgbaImage {
+        RgbaImage::from_pixel(4, 4, Rgba([shade, shade, shade, 255]))
+    }
+
+    /// Frames of a single shade, 0 showing nothing, 1 success, 2 failure and
+    /// 3 the next fingerprint.
+    fn wait(shades: &[u8]) -> Outcome {
+        let mut frames =
+            ReplaySource::from_frames(shades.iter().map(|shade| frame(*shade)).collect()).unwrap();
+        wait_for_outcome(
+            &mut frames,
+            &ThreadStatus::new(),
+            &[[0, 0, 4, 4]],
+            Duration::from_secs(5),
+            |crops| {
+                Ok(match crops[0].get_pixel(0, 0)[0] {
+                    1 => Some(ResultScreen::Success),
+                    2 => Some(ResultScreen::Failure),
+                    3 => Some(ResultScreen::NextFingerprint),
+                    _ => None,
+                })
+            },
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn waits_for_the_result_to_go_away() {
+        assert_eq!(wait(&[0, 1, 1, 0, 3]), Outcome::Success);
+        assert_eq!(wait(&[0, 2, 0, 0]), Outcome::Failure);
+        assert_eq!(wait(&[0, 0, 3]), Outcome::Success);
+        assert_eq!(wait(&[0, 0, 0]), Outcome::Unknown);
+    }
+
+    #[test]
+    fn recognizes_the_closest_result_screen() {
+        let matcher = MatcherConfig::Ncc { size: 8 }.build().unwrap();
+        let image = |step: u32| {
+            RgbImage::from_fn(16, 16, |x, y| {
+                let value = ((x * step + y * 3) % 256) as u8;
+                Rgb([value, value, value])
+            })
+        };
+        let results = vec![
+            (ResultScreen::Success, matcher.features(&image(16))),
+            (ResultScreen::Failure, matcher.features(&image(5))),
+        ];
+        let recognize = |step| {
+            recognize_result(
+                matcher.as_ref(),
+                &results,
+                &matcher.features(&image(step)),
+                0.95,
+            )
+            .unwrap()
+        };
+        assert_eq!(recognize(5), Some(ResultScreen::Failure));
+        assert_eq!(recognize(16), Some(ResultScreen::Success));
+        assert_eq!(recognize(0), None);
+    }
+}
@@ -0,0 +1,221 @@
+use crate::error::TaskError;
+use crate::matcher::MatcherConfig;
+use crate::utils::{self, Asset};
+use log::warn;
+use serde::Deserialize;
+use std::fs;
+use toml::Table;
+
+const EMBEDDED_THRESHOLDS: &str = "assets/thresholds.toml";
+
+/// Similarities a fingerprint solver needs before trusting what it sees, and
+/// how it measures them.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TaskThresholds {
+    pub matcher: MatcherConfig,
+    /// Header similarity above which the fingerprint screen is shown.
+    pub header: f64,
+    /// Same as `header`, when crops are rescaled to reference images made for
+    /// another resolution.
+    pub rescaled_header: f64,
+    /// Smallest similarity of the best matching fingerprint.
+    pub fingerprint: f64,
+    /// Smallest lead of the best matching fingerprint over the second one.
+    pub fingerprint_margin: f64,
+    /// Smallest similarity of every matched part.
+    pub part: f64,
+    /// Smallest lead of the matched parts over the next best match, for the
+    /// casino over the best other set of slots.
+    pub part_margin: f64,
+    /// Similarity above which a result screen is shown.
+    pub result: f64,
+    /// Casino only: mean change of the center of a slot, from 0 to 1, above
+    /// which the game shows it selected.
+    #[serde(default)]
+    pub selected_change: Option<f64>,
+}
+
+impl TaskThresholds {
+    /// Refuses values outside of 0 to 1, which would make the solver always
+    /// or never act.
+    fn validate(&self, task: &str) -> Result<(), TaskError> {
+        let values = [
+            ("header", self.header),
+            ("rescaled_header", self.rescaled_header),
+            ("fingerprint", self.fingerprint),
+            ("fingerprint_margin", self.fingerprint_margin),
+            ("part", self.part),
+            ("part_margin", self.part_margin),
+            ("result", self.result),
+        ];
+        let values = values
+            .into_iter()
+            .chain(self.selected_change.map(|value| ("selected_change", value)));
+        for (name, value) in values {
+            if !(0.0..=1.0).contains(&value) {
+                return Err(TaskError::Thresholds(format!(
+                    "{}.{} is {}, it must be between 0 and 1",
+                    task, name, value
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Thresholds {
+    pub casino: TaskThresholds,
+    pub cayo: TaskThresholds,
+}
+
+impl Thresholds {
+    /// Thresholds shipped with the executable.
+    pub fn embedded() -> Result<Self, TaskError> {
+        Self::resolve(parse(&embedded_source()?)?)
+    }
+
+    /// Embedded thresholds with the values of the user's `thresholds.toml`
+    /// merged over them.
+    pub fn load() -> Result<Self, TaskError> {
+        let mut table = parse(&embedded_source()?)?;
+        if let Some(path) = utils::config_path("thresholds.toml") {
+            if path.exists() {
+                let overrides = parse(&fs::read_to_string(&path)?)
+                    .map_err(|err| TaskError::Thresholds(format!("{}: {}", path.display(), err)))?;
+                for task in untuned_matchers(&table, &overrides) {
+                    warn!(
+                        "{} changes the {} matcher but keeps the thresholds tuned for the \
+                         built-in one, they likely need adjusting too",
+                        path.display(),
+                        task
+                    );
+                }
+                merge(&mut table, overrides);
+            }
+        }
+        Self::resolve(table)
+    }
+
+    fn resolve(table: Table) -> Result<Self, TaskError> {
+        let thresholds: Self = table
+            .try_into()
+            .map_err(|err: toml::de::Error| TaskError::Thresholds(err.to_string()))?;
+        thresholds.casino.validate("casino")?;
+        thresholds.cayo.validate("cayo")?;
+        if thresholds.casino.selected_change.is_none() {
+            return Err(TaskError::Thresholds(
+                "casino.selected_change is missing".to_string(),
+            ));
+        }
+        if thresholds.cayo.selected_change.is_some() {
+            return Err(TaskError::Thresholds(
+                "cayo.selected_change only applies to the casino".to_string(),
+            ));
+        }
+        Ok(thresholds)
+    }
+}
+
+fn embedded_source() -> Result<String, TaskError> {
+    let file = Asset::get(EMBEDDED_THRESHOLDS)
+        .ok_or(TaskError::AssetMissing(EMBEDDED_THRESHOLDS.to_string()))?;
+    Ok(String::from_utf8_lossy(&file.data).into_owned())
+}
+
+fn parse(source: &str) -> Result<Table, TaskError> {
+    source
+        .parse()
+        .map_err(|err: toml::de::Error| TaskError::Thresholds(err.to_string()))
+}
+
+/// Tasks whose matcher `overrides` changes from the one of `table` without
+/// changing any of its thresholds.
+fn untuned_matchers<'a>(table: &Table, overrides: &'a Table) -> Vec<&'a str> {
+    overrides
+        .iter()
+        .filter_map(|(task, values)| {
+            let values = values.as_table()?;
+            let matcher = values.get("matcher")?;
+            let current = table.get(task)?.as_table()?;
+            (current.get("matcher") != Some(matcher) && values.len() == 1).then_some(task.as_str())
+        })
+        .collect()
+}
+
+/// Merges `overrides` over `table` value by value, so that a user file can
+/// change a single threshold.
+fn merge(table: &mut Table, overrides: Table) {
+    for (task, values) in overrides {
+        match (table.get_mut(&task), values) {
+            (Some(toml::Value::Table(current)), toml::Value::Table(values)) => {
+                current.extend(values)
+            }
+            (_, values) => {
+                table.insert(task, values);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overrides_replace_single_values() {
+        let mut table = parse(&embedded_source().unwrap()).unwrap();
+        let embedded = Thresholds::resolve(table.clone()).unwrap();
+        merge(&mut table, parse("[cayo]\npart = 0.5").unwrap());
+        let merged = Thresholds::resolve(table).unwrap();
+        assert_eq!(merged.cayo.part, 0.5);
+        assert_eq!(merged.cayo.header, embedded.cayo.header);
+        assert_eq!(merged.casino, embedded.casino);
+    }
+
+    #[test]
+    fn unknown_values_are_rejected() {
+        let mut table = parse(&embedded_source().unwrap()).unwrap();
+        merge(&mut table, parse("[casino]\nheadr = 0.5").unwrap());
+        assert!(Thresholds::resolve(table).is_err());
+    }
+
+    #[test]
+    fn matchers_changed_alone_are_reported() {
+        let table = parse(&embedded_source().unwrap()).unwrap();
+        let overrides = parse(
+            "[casino]\nmatcher = { kind = \"ncc\", size = 32 }\n\
+             [cayo]\nmatcher = { kind = \"ncc\", size = 32 }\npart = 0.9",
+        )
+        .unwrap();
+        assert_eq!(untuned_matchers(&table, &overrides), vec!["casino"]);
+        let same =
+            parse("[cayo]\nmatcher = { kind = \"hash\", algorithm = \"gradient\", size = 8 }")
+                .unwrap();
+        assert!(untuned_matchers(&table, &same).is_empty());
+    }
+
+    #[test]
+    fn values_out_of_range_are_rejected() {
+        for (task, value) in [
+            ("cayo", "part = 2"),
+            ("cayo", "part_margin = -0.1"),
+            ("cayo", "header = nan"),
+            ("casino", "selected_change = 1.5"),
+            ("cayo", "selected_change = 0.1"),
+        ] {
+            let mut table = parse(&embedded_source().unwrap()).unwrap();
+            merge(
+                &mut table,
+                parse(&format!("[{}]\n{}", task, value)).unwrap(),
+            );
+            assert!(
+                matches!(Thresholds::resolve(table), Err(TaskError::Thresholds(_))),
+                "{}",
+                value
+            );
+        }
+    }
+}
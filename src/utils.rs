@@ -1,24 +1,32 @@
-use crate::constants;
-use image::{DynamicImage, ImageReader, RgbImage, imageops};
-use image_hasher::{Hasher, HasherConfig, ImageHash};
-use log::error;
-use rust_embed::Embed;
-use std::path::{Component, PathBuf};
-use std::sync::{Arc, Mutex};
-use std::{panic, thread};
-use windows::Win32::UI::Input::KeyboardAndMouse::{
-    INPUT, INPUT_0, INPUT_KEYBOARD, KEYBD_EVENT_FLAGS, KEYBDINPUT, KEYEVENTF_EXTENDEDKEY,
-    KEYEVENTF_KEYUP, MAPVK_VK_TO_VSC, MapVirtualKeyA, SendInput, VIRTUAL_KEY, VK_DELETE, VK_DOWN,
-    VK_END, VK_HOME, VK_INSERT, VK_LEFT, VK_NEXT, VK_PAUSE, VK_PRIOR, VK_RCONTROL, VK_RIGHT, VK_UP,
+use crate::capture::FrameSource;
+use crate::error::TaskError;
+use crate::input::InputBackend;
+use directories::ProjectDirs;
+use image::{
+    DynamicImage, ImageBuffer, ImageReader, Pixel, Rgb, RgbImage, RgbaImage, imageops,
+    imageops::FilterType,
 };
+use image_hasher::ImageHash;
+use lazy_static::lazy_static;
+use log::warn;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use rust_embed::Embed;
+use std::borrow::Cow;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+use std::{env, fs, thread};
 use xcap::Monitor;
 
-pub type ThreadStatus = Arc<Mutex<bool>>;
 pub type Region = [u32; 4];
 pub type Resolution = (u32, u32);
 pub struct TaskData {
     pub thread_status: ThreadStatus,
     pub button: Button,
+    /// Stop after solving a single fingerprint.
+    pub once: bool,
+    pub input: Box<dyn InputBackend>,
+    pub frames: Box<dyn FrameSource>,
 }
 #[derive(Clone, Hash, PartialEq, Eq)]
 pub enum ButtonType {
@@ -26,239 +34,310 @@ pub enum ButtonType {
     Timer(u32),
 }
 
-pub type TaskResult = thread::JoinHandle<()>;
+pub type TaskResult = thread::JoinHandle<Result<(), TaskError>>;
 #[derive(Clone, Hash, PartialEq, Eq)]
 pub struct Button {
+    /// Name of the task in configuration files.
+    pub id: &'static str,
     pub task: fn(TaskData) -> TaskResult,
     pub enabled_text: &'static str,
     pub disabled_text: &'static str,
     pub btn_type: ButtonType,
 }
 
+lazy_static! {
+    /// Workers shared by the image comparisons of every task, one per cpu
+    /// unless the `ASSISTANT_THREADS` environment variable says otherwise.
+    pub(crate) static ref MATCH_POOL: ThreadPool = {
+        let threads = match env::var("ASSISTANT_THREADS") {
+            Ok(threads) => threads.parse().unwrap_or_else(|_| {
+                warn!("Ignoring ASSISTANT_THREADS={:?}, expected a number", threads);
+                0
+            }),
+            Err(_) => 0,
+        };
+        ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .thread_name(|i| format!("matcher {}", i))
+            .build()
+            .expect("failed to start the matching threads")
+    };
+}
+
 #[derive(Embed)]
 #[folder = "assets"]
 #[prefix = "assets/"]
 pub struct Asset;
 
-pub fn check_thread_status(thread_status: &ThreadStatus) -> bool {
-    let signal = thread_status.lock().unwrap();
-    *signal
+/// Whether a task should keep running, shared between the task and whoever
+/// can stop it. Stopping wakes up the task right away, even when it sleeps.
+#[derive(Clone)]
+pub struct ThreadStatus {
+    inner: Arc<(Mutex<bool>, Condvar)>,
 }
 
-pub fn get_main_monitor() -> Result<Monitor, String> {
-    let monitors = Monitor::all();
-    let monitors = match monitors {
-        Ok(monitors) => monitors,
-        Err(err) => {
-            return Err(err.to_string());
-        }
-    };
-    let main_monitor = monitors
-        .into_iter()
-        .find(|monitor| monitor.is_primary().unwrap());
-    let main_monitor = match main_monitor {
-        Some(monitor) => Ok(monitor),
-        None => {
-            return Err("No primary monitor found".to_string());
+impl ThreadStatus {
+    pub fn new() -> Self {
+        ThreadStatus {
+            inner: Arc::new((Mutex::new(true), Condvar::new())),
         }
-    };
-    main_monitor
+    }
+
+    pub fn is_running(&self) -> bool {
+        *self.inner.0.lock().unwrap()
+    }
+
+    pub fn stop(&self) {
+        let (running, stopped) = &*self.inner;
+        *running.lock().unwrap() = false;
+        stopped.notify_all();
+    }
+
+    /// Waits for `duration` unless the task is stopped before, returns
+    /// whether it is still running.
+    pub fn sleep(&self, duration: Duration) -> bool {
+        let (running, stopped) = &*self.inner;
+        let running = stopped
+            .wait_timeout_while(running.lock().unwrap(), duration, |running| *running)
+            .unwrap()
+            .0;
+        *running
+    }
 }
 
-pub fn get_resolution() -> Resolution {
-    let monitor = get_main_monitor().unwrap();
-    return (monitor.width().unwrap(), monitor.height().unwrap());
+impl Default for ThreadStatus {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-pub fn capture_region(monitor: &Monitor, region: &[u32; 4]) -> image::DynamicImage {
-    let image = monitor.capture_image();
-    let mut image = match image {
-        Ok(image) => image,
-        Err(err) => {
-            panic!("Failed to capture image: {}", err);
-        }
-    };
-    let image = imageops::crop(
-        &mut image,
+/// Path of a user configuration file, merged over the embedded defaults when
+/// present.
+pub fn config_path(file_name: &str) -> Option<PathBuf> {
+    ProjectDirs::from("fr", "kensa", "gta-assistant").map(|dirs| dirs.config_dir().join(file_name))
+}
+
+pub fn get_main_monitor() -> Result<Monitor, TaskError> {
+    let monitors = Monitor::all().map_err(|err| TaskError::CaptureFailed(err.to_string()))?;
+    monitors
+        .into_iter()
+        .find(|monitor| monitor.is_primary().unwrap_or(false))
+        .ok_or(TaskError::CaptureFailed(
+            "No primary monitor found".to_string(),
+        ))
+}
+
+pub fn crop_region<P>(image: &ImageBuffer<P, Vec<u8>>, region: &Region) -> RgbImage
+where
+    P: Pixel<Subpixel = u8> + 'static,
+    DynamicImage: From<ImageBuffer<P, Vec<u8>>>,
+{
+    let image = imageops::crop_imm(
+        image,
         region[0],
         region[1],
         region[2] - region[0],
         region[3] - region[1],
     );
-    DynamicImage::ImageRgba8(image.to_image())
+    DynamicImage::from(image.to_image()).into_rgb8()
 }
 
-pub fn capture_regions(monitor: &Monitor, regions: &[Region]) -> Vec<image::DynamicImage> {
-    let image = monitor.capture_image();
-    let mut image = match image {
-        Ok(image) => image,
-        Err(err) => {
-            panic!("Failed to capture image: {}", err);
-        }
-    };
-    regions
-        .iter()
-        .map(|region| {
-            let image = imageops::crop(
-                &mut image,
-                region[0],
-                region[1],
-                region[2] - region[0],
-                region[3] - region[1],
-            );
-            DynamicImage::ImageRgba8(image.to_image())
-        })
-        .collect()
+/// Copies `region` of `image`, whose top left corner is at `origin` on the
+/// screen, into `crop`, only reallocating it when its size changes. Like
+/// `crop_region`, the part of the region past the image is left out.
+pub fn crop_into(image: &RgbaImage, origin: (u32, u32), region: &Region, crop: &mut RgbImage) {
+    let left = region[0].saturating_sub(origin.0).min(image.width());
+    let top = region[1].saturating_sub(origin.1).min(image.height());
+    let width = (region[2] - region[0]).min(image.width() - left);
+    let height = (region[3] - region[1]).min(image.height() - top);
+    if crop.dimensions() != (width, height) {
+        *crop = RgbImage::new(width, height);
+    }
+    for (x, y, pixel) in crop.enumerate_pixels_mut() {
+        let [r, g, b, _] = image.get_pixel(left + x, top + y).0;
+        *pixel = Rgb([r, g, b]);
+    }
 }
 
-fn hash_image(hasher: &Hasher, img: &RgbImage) -> ImageHash {
-    hasher.hash_image(img)
+/// Smallest region containing all of `regions`.
+pub fn union_region(regions: &[Region]) -> Option<Region> {
+    regions.iter().copied().reduce(|union, region| {
+        [
+            union[0].min(region[0]),
+            union[1].min(region[1]),
+            union[2].max(region[2]),
+            union[3].max(region[3]),
+        ]
+    })
 }
 
-pub fn compare_image(img1: &RgbImage, img2: &RgbImage) -> f64 {
-    let hasher = HasherConfig::new().to_hasher();
-    let (hash1, hash2) = thread::scope(|scope| {
-        let hash1_thread = scope.spawn(|| hash_image(&hasher, img1));
-        let hash2_thread = scope.spawn(|| hash_image(&hasher, img2));
-        let hash1 = hash1_thread.join().unwrap();
-        let hash2 = hash2_thread.join().unwrap();
-        (hash1, hash2)
-    });
-
-    let distance = hash1.dist(&hash2);
-    let similarity = 1.0 - (distance as f64 / (hash1.as_bytes().len() * 8) as f64);
-    similarity
+/// Resizes `image` by `factor`, borrowing it when its size does not change.
+pub fn rescale(image: &RgbImage, factor: f64) -> Cow<'_, RgbImage> {
+    let width = (image.width() as f64 * factor).round().max(1.0) as u32;
+    let height = (image.height() as f64 * factor).round().max(1.0) as u32;
+    if (width, height) == image.dimensions() {
+        Cow::Borrowed(image)
+    } else {
+        Cow::Owned(imageops::resize(image, width, height, FilterType::Triangle))
+    }
 }
 
-pub fn find_image_in_array(target: &RgbImage, images: &[RgbImage]) -> usize {
-    thread::scope(|scope| {
-        let mut threads = Vec::with_capacity(images.len());
-        for (index, image) in images.iter().enumerate() {
-            threads.push(scope.spawn(move || {
-                let score = compare_image(target, image);
-                (index, score)
-            }))
-        }
-
-        let mut best_score = 0f64;
-        let mut best_index = 0;
-        for thread in threads {
-            let (index, score) = thread.join().unwrap();
-            if score > best_score {
-                best_score = score;
-                best_index = index;
-            }
-            if score == 1f64 {
-                return index;
-            }
-        }
-        return best_index;
-    })
+/// Mean difference between the pixels of two images of the same size, from 0
+/// (same) to 1.
+pub fn mean_difference(a: &RgbImage, b: &RgbImage) -> f64 {
+    if a.dimensions() != b.dimensions() {
+        return 1.0;
+    }
+    let total: u64 = a
+        .as_raw()
+        .iter()
+        .zip(b.as_raw())
+        .map(|(a, b)| a.abs_diff(*b) as u64)
+        .sum();
+    total as f64 / a.as_raw().len().max(1) as f64 / 255.0
 }
 
-pub fn press(vk_code: VIRTUAL_KEY) {
-    fn send(vk_code: VIRTUAL_KEY, down: bool) {
-        let extended_keys = vec![
-            VK_RCONTROL,
-            VK_PAUSE,
-            VK_HOME,
-            VK_PRIOR,
-            VK_UP,
-            VK_LEFT,
-            VK_DOWN,
-            VK_RIGHT,
-            VK_NEXT,
-            VK_END,
-            VK_INSERT,
-            VK_DELETE,
-        ];
-        let mut flags = if down {
-            KEYBD_EVENT_FLAGS(0)
-        } else {
-            KEYEVENTF_KEYUP
-        };
-        if extended_keys.contains(&vk_code) {
-            flags |= KEYEVENTF_EXTENDEDKEY;
-        }
-        let scan = unsafe { MapVirtualKeyA(vk_code.0 as u32, MAPVK_VK_TO_VSC) };
+/// Share of identical bits between two hashes, from 0 to 1.
+pub fn hash_similarity(hash1: &ImageHash, hash2: &ImageHash) -> f64 {
+    let distance = hash1.dist(hash2);
+    1.0 - (distance as f64 / (hash1.as_bytes().len() * 8) as f64)
+}
 
-        let input = INPUT {
-            r#type: INPUT_KEYBOARD,
-            Anonymous: INPUT_0 {
-                ki: KEYBDINPUT {
-                    wVk: VIRTUAL_KEY(0),
-                    wScan: scan as u16,
-                    dwFlags: flags,
-                    time: 0,
-                    dwExtraInfo: 0,
-                },
-            },
-        };
-        unsafe {
-            SendInput(&[input], size_of::<INPUT>() as i32);
-        }
-    }
-    send(vk_code, true);
-    thread::sleep(*constants::PRESS_DURATION);
-    send(vk_code, false);
-    thread::sleep(*constants::PRESS_DURATION);
+/// Best of a list of similarities, with the second best to tell how clear
+/// the match is.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Match {
+    pub index: usize,
+    pub score: f64,
+    /// Second best similarity, negative infinity when there is a single one.
+    pub second: f64,
 }
 
-pub fn multiple_press(key: VIRTUAL_KEY, count: usize) {
-    for _ in 0..count {
-        press(key);
+impl Match {
+    /// How much the best similarity beats the second one.
+    pub fn margin(&self) -> f64 {
+        self.score - self.second
     }
 }
-pub fn relative_array(arr: &[usize]) -> Vec<usize> {
-    let mut result = Vec::new();
-    let mut last = 0;
-    for i in arr {
-        result.push(i - last);
-        last = *i;
+
+pub fn best_match(scores: &[f64]) -> Match {
+    let mut best = Match {
+        index: 0,
+        score: f64::NEG_INFINITY,
+        second: f64::NEG_INFINITY,
+    };
+    for (index, score) in scores.iter().enumerate() {
+        if *score > best.score {
+            best.second = best.score;
+            best.score = *score;
+            best.index = index;
+        } else if *score > best.second {
+            best.second = *score;
+        }
     }
-    result
+    best
 }
 
-pub fn load_image(path: PathBuf) -> RgbImage {
+pub fn load_image(path: PathBuf) -> Result<RgbImage, TaskError> {
     let mut components = path.components();
     match components.next() {
-        Some(Component::Normal(s)) if s.to_str().unwrap() == "assets" => {
+        Some(Component::Normal(s)) if s.to_str() == Some("assets") => {
             let asset_path = path
                 .components()
-                .map(|c| c.as_os_str().to_str().unwrap())
-                .collect::<Vec<&str>>()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
                 .join("/");
             let file = match Asset::get(&asset_path) {
                 Some(file) => file,
-                None => {
-                    error!("failed to get asset");
-                    panic!("failed to get asset");
-                }
+                None => return Err(TaskError::AssetMissing(asset_path)),
             };
 
             match image::load_from_memory(&file.data) {
-                Ok(img) => img.to_rgb8(),
-                Err(err) => {
-                    error!("error while decoding image : {}", err);
-                    panic!("error while decoding image");
-                }
+                Ok(img) => Ok(img.to_rgb8()),
+                Err(err) => Err(TaskError::AssetDecode(format!("{}: {}", asset_path, err))),
             }
         }
         _ => {
-            let img = match ImageReader::open(path) {
+            let img = match ImageReader::open(&path) {
                 Ok(img) => img,
-                Err(err) => {
-                    error!("failed to open image : {}", err);
-                    panic!("");
-                }
+                Err(_) => return Err(TaskError::AssetMissing(path.display().to_string())),
             };
             match img.decode() {
-                Ok(img) => img.to_rgb8(),
-                Err(err) => {
-                    error!("failed to decode image : {}", err);
-                    panic!("");
-                }
+                Ok(img) => Ok(img.to_rgb8()),
+                Err(err) => Err(TaskError::AssetDecode(format!(
+                    "{}: {}",
+                    path.display(),
+                    err
+                ))),
             }
         }
     }
 }
+
+/// Folders of `folder` named after a number, like the ones the capture
+/// tasks create, sorted by that number. Anything else is skipped.
+pub fn numbered_folders(folder: &Path) -> Result<Vec<(usize, PathBuf)>, TaskError> {
+    let mut folders = Vec::new();
+    for entry in fs::read_dir(folder)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        if let Some(index) = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.parse().ok())
+        {
+            folders.push((index, entry.path()));
+        }
+    }
+    folders.sort();
+    Ok(folders)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_numbered_folders_are_listed() {
+        let folder = env::temp_dir().join(format!("gta-assistant-folders-{}", std::process::id()));
+        for name in ["10", "2", "selection", "3.5"] {
+            fs::create_dir_all(folder.join(name)).unwrap();
+        }
+        fs::write(folder.join("4"), "").unwrap();
+        let folders = numbered_folders(&folder);
+        fs::remove_dir_all(&folder).unwrap();
+        assert_eq!(
+            folders.unwrap(),
+            vec![(2, folder.join("2")), (10, folder.join("10"))]
+        );
+        assert!(matches!(numbered_folders(&folder), Err(TaskError::Io(_))));
+    }
+
+    #[test]
+    fn best_match_keeps_the_second_score() {
+        let best = best_match(&[0.2, 0.9, 0.4, 0.9]);
+        assert_eq!(best.index, 1);
+        assert_eq!(best.score, 0.9);
+        assert_eq!(best.second, 0.9);
+        assert_eq!(best_match(&[0.0, 0.0]).margin(), 0.0);
+        assert_eq!(best_match(&[0.5]).second, f64::NEG_INFINITY);
+    }
+
+    #[test]
+    fn stopping_wakes_up_sleeping_tasks() {
+        let status = ThreadStatus::new();
+        assert!(status.sleep(Duration::from_millis(1)));
+        let stopper = status.clone();
+        let start = std::time::Instant::now();
+        let thread = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            stopper.stop();
+        });
+        assert!(!status.sleep(Duration::from_secs(20)));
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(!status.is_running());
+        thread.join().unwrap();
+    }
+}
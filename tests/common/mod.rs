@@ -0,0 +1,176 @@
+use gta_assistant::layout::{Layouts, TaskLayout};
+use gta_assistant::screen::{ScreenClassifier, ScreenWatcher};
+use gta_assistant::solver::outcome::ResultScreen;
+use gta_assistant::solver::{FingerprintSolver, reference_assets};
+use gta_assistant::thresholds::Thresholds;
+use gta_assistant::utils::{self, Region, Resolution};
+use image::{DynamicImage, RgbImage, RgbaImage, imageops};
+use std::fmt::Debug;
+use std::path::PathBuf;
+
+/// Resolutions without reference images of their own, covering smaller and
+/// bigger 16:9 screens, ultrawide (pillarbox) and 16:10 (letterbox).
+pub const RESCALED_RESOLUTIONS: [Resolution; 4] =
+    [(1600, 900), (3840, 2160), (3440, 1440), (1920, 1200)];
+
+pub fn blank_frame(resolution: Resolution) -> RgbImage {
+    RgbImage::new(resolution.0, resolution.1)
+}
+
+pub fn rgba(frame: RgbImage) -> RgbaImage {
+    DynamicImage::ImageRgb8(frame).into_rgba8()
+}
+
+/// Watcher of the screens of the embedded configuration.
+pub fn watcher(resolution: Resolution) -> ScreenWatcher {
+    let layout = Layouts::embedded().unwrap().get(resolution);
+    let thresholds = Thresholds::embedded().unwrap();
+    ScreenWatcher::new(ScreenClassifier::with_config(resolution, &layout, &thresholds).unwrap())
+}
+
+pub fn paste(frame: &mut RgbImage, image: &RgbImage, region: &Region) {
+    imageops::replace(frame, image, region[0] as i64, region[1] as i64);
+}
+
+/// Pastes a reference image resized by `factor`, to build frames of
+/// resolutions the image was not made for.
+pub fn paste_asset(frame: &mut RgbImage, asset: PathBuf, region: &Region, factor: f64) {
+    let image = utils::load_image(asset).unwrap();
+    paste(frame, &utils::rescale(&image, factor), region);
+}
+
+/// Frame of `task` showing its header and the `fingerprint_file` of
+/// `fingerprint` (0 based), with the reference folder of the task and the
+/// factor turning screen crops into the size of its images.
+pub fn fingerprint_frame(
+    resolution: Resolution,
+    task: &str,
+    layout: &TaskLayout,
+    fingerprint: usize,
+    fingerprint_file: &str,
+) -> (RgbImage, PathBuf, f64) {
+    let (folder, scale) = reference_assets(resolution, task);
+    let mut frame = blank_frame(resolution);
+    paste_asset(
+        &mut frame,
+        folder.join("header.png"),
+        &layout.header,
+        1.0 / scale,
+    );
+    paste_asset(
+        &mut frame,
+        folder
+            .join((fingerprint + 1).to_string())
+            .join(fingerprint_file),
+        &layout.fingerprint,
+        1.0 / scale,
+    );
+    (frame, folder, scale)
+}
+
+/// Builds `shuffles` frames of each of the `fingerprint_count` fingerprints
+/// and checks that `solve` finds the fingerprint and the placement of the
+/// parts `build` returned.
+pub fn check_fixtures<T: PartialEq + Debug>(
+    fingerprint_count: usize,
+    shuffles: u64,
+    build: impl Fn(usize, &mut Shuffler) -> (RgbImage, T),
+    solve: impl Fn(&RgbImage) -> (usize, T),
+) {
+    for fingerprint in 0..fingerprint_count {
+        for seed in 0..shuffles {
+            let (frame, placement) = build(fingerprint, &mut Shuffler::new(seed));
+            let (found, found_placement) = solve(&frame);
+            assert_eq!(
+                found, fingerprint,
+                "fingerprint {} seed {}",
+                fingerprint, seed
+            );
+            assert_eq!(
+                found_placement, placement,
+                "fingerprint {} seed {}",
+                fingerprint, seed
+            );
+        }
+    }
+}
+
+/// Checks that the result crops of `solver` show the next fingerprint once
+/// the frame built for fingerprint 0 is replaced by the one of fingerprint 1,
+/// and nothing before that or on a blank frame.
+pub fn check_next_fingerprint(
+    solver: &FingerprintSolver,
+    build: impl Fn(usize, &mut Shuffler) -> RgbImage,
+) {
+    let regions = solver.result_regions_pos();
+    let crops = |frame: &RgbImage| -> Vec<RgbImage> {
+        regions
+            .iter()
+            .map(|region| utils::crop_region(frame, region))
+            .collect()
+    };
+    let solved = build(0, &mut Shuffler::new(0));
+    let next = build(1, &mut Shuffler::new(1));
+
+    assert_eq!(solver.result_screen(&crops(&solved), 0).unwrap(), None);
+    let blank = blank_frame(solver.resolution());
+    assert_eq!(solver.result_screen(&crops(&blank), 0).unwrap(), None);
+    assert_eq!(
+        solver.result_screen(&crops(&next), 0).unwrap(),
+        Some(ResultScreen::NextFingerprint)
+    );
+}
+
+/// Checks that `recognize` tells apart the 1080p screenshots of success and
+/// failure screens saved as `results/{success,failure}_N.png` in the asset
+/// folder of `task`, once cropped to the result `regions` of its solver.
+pub fn check_captured_results(
+    task: &str,
+    regions: &[Region],
+    recognize: impl Fn(&[RgbImage]) -> Option<ResultScreen>,
+) {
+    assert_eq!(regions.len(), 3, "the {} layout has no result region", task);
+    let folder = reference_assets((1920, 1080), task).0.join("results");
+    let mut checked = 0;
+    for (screen, name) in [
+        (ResultScreen::Success, "success"),
+        (ResultScreen::Failure, "failure"),
+    ] {
+        for index in 1.. {
+            let Ok(frame) = utils::load_image(folder.join(format!("{}_{}.png", name, index)))
+            else {
+                break;
+            };
+            let crops: Vec<RgbImage> = regions
+                .iter()
+                .map(|region| utils::crop_region(&frame, region))
+                .collect();
+            assert_eq!(recognize(&crops), Some(screen), "{}_{}", name, index);
+            checked += 1;
+        }
+    }
+    assert!(checked > 0, "no captured results in {}", folder.display());
+}
+
+/// Small deterministic generator so the shuffles are the same on every run.
+pub struct Shuffler(u64);
+
+impl Shuffler {
+    pub fn new(seed: u64) -> Self {
+        Shuffler(seed.wrapping_mul(0x9E3779B97F4A7C15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            let j = (self.next() % (i as u64 + 1)) as usize;
+            items.swap(i, j);
+        }
+    }
+}